@@ -49,8 +49,8 @@ use pnet::packet::ipv4::Ipv4Packet;
 use pnet::packet::udp::UdpPacket;
 use byteorder::{BigEndian, ReadBytesExt};
 
-use hyperscan::{BlockDatabase, BlockScanner, Database, DatabaseBuilder, Pattern, Patterns, RawScratch, RawStream,
-                Scratch, ScratchAllocator, Stream, StreamingDatabase, StreamingScanner};
+use hyperscan::{BlockDatabase, BlockScanner, Database, DatabaseBundle, Pattern, Patterns, RawScratch, RawStream,
+                Scratch, ScratchAllocator, Stream, StreamingDatabase, StreamingScanner, HS_MODE_BLOCK, HS_MODE_STREAM};
 
 #[derive(Debug)]
 enum Error {
@@ -98,24 +98,10 @@ impl Milliseconds for Duration {
     }
 }
 
-macro_rules! build_database {
-    ($builder:expr, $mode:expr) => ({
-        let now = Instant::now();
-
-        let db = try!($builder.build());
-
-        println!("Hyperscan {} mode database compiled in {}ms",
-             $mode,
-             now.elapsed().ms());
-
-        db
-    })
-}
-
 /**
  * This function will read in the file with the specified name, with an
- * expression per line, ignoring lines starting with '#' and build a Hyperscan
- * database for it.
+ * expression per line, ignoring lines starting with '#' and build the Hyperscan
+ * streaming and block mode databases for it in one bundle.
  */
 fn databases_from_file(filename: &str) -> Result<(StreamingDatabase, BlockDatabase), Error> {
     // do the actual file reading and string handling
@@ -126,10 +112,14 @@ fn databases_from_file(filename: &str) -> Result<(StreamingDatabase, BlockDataba
         patterns.len()
     );
 
-    Ok((
-        build_database!(patterns, "streaming"),
-        build_database!(patterns, "block"),
-    ))
+    let now = Instant::now();
+
+    let mut bundle = try!(DatabaseBundle::build(patterns, HS_MODE_STREAM | HS_MODE_BLOCK));
+
+    println!("Hyperscan streaming and block mode databases compiled in {}ms",
+             now.elapsed().ms());
+
+    Ok((bundle.take_streaming().unwrap(), bundle.take_block().unwrap()))
 }
 
 fn parse_file(filename: &str) -> Result<Patterns, io::Error> {
@@ -313,7 +303,7 @@ impl Benchmark {
 
     // Close all open Hyperscan streams (potentially generating any end-anchored matches)
     fn close_streams(&mut self) {
-        for ref stream in &self.streams {
+        for stream in self.streams.drain(..) {
             if let Err(err) = stream.close(&self.scratch, Some(Self::on_match), Some(&self.match_count)) {
                 println!("ERROR: Unable to close stream. Exiting. {}", err);
             }
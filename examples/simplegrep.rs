@@ -2,8 +2,8 @@
 //
 // This is a simple example of Hyperscan's most basic functionality: it will
 // search a given input file for a pattern supplied as a command-line argument.
-// It is intended to demonstrate correct usage of the hs_compile and hs_scan
-// functions of Hyperscan.
+// It is intended to demonstrate correct usage of the hs_compile and
+// hs_scan_stream functions of Hyperscan.
 //
 // Patterns are scanned in 'DOTALL' mode, which is equivalent to PCRE's '/s'
 // modifier. This behaviour can be changed by modifying the "flags" argument to
@@ -28,25 +28,15 @@ extern crate hyperscan;
 
 use std::env;
 use std::io;
-use std::io::{Read, Write};
+use std::io::Write;
 use std::fs::File;
 use std::process::exit;
 use std::path::Path;
 
 use hyperscan::*;
 
-/**
- * Fill a data buffer from the given filename, returning it and filling @a
- * length with its length. Returns NULL on failure.
- */
-fn read_input_data(input_filename: &str) -> Result<String, io::Error> {
-    let mut f = try!(File::open(input_filename));
-    let mut buf = String::new();
-
-    try!(f.read_to_string(&mut buf));
-
-    Ok(buf)
-}
+/// The size of the chunks read from the input file.
+const BUF_SIZE: usize = 64 * 1024;
 
 #[allow(unused_must_use)]
 fn main() {
@@ -69,7 +59,7 @@ fn main() {
     let pattern = pattern!(args.next().unwrap(), flags => HS_FLAG_DOTALL);
     let input_filename = args.next().unwrap();
 
-    let database: BlockDatabase = match pattern.build() {
+    let database: StreamingDatabase = match pattern.build() {
         Ok(db) => db,
         Err(err) => {
             write!(io::stderr(),
@@ -80,9 +70,10 @@ fn main() {
         }
     };
 
-    // Next, we read the input data file into a buffer.
-    let input_data = match read_input_data(&input_filename) {
-        Ok(buf) => buf,
+    // Next, we open the input data file, which will be read in chunks, so
+    // that files larger than the memory can be scanned.
+    let input_file = match File::open(&input_filename) {
+        Ok(f) => f,
         Err(err) => {
             write!(io::stderr(),
                    "ERROR: Unable to read file `{}`: {}\n",
@@ -92,34 +83,22 @@ fn main() {
         }
     };
 
-    // Finally, we issue a call to hs_scan, which will search the input buffer
-    // for the pattern represented in the bytecode. Note that in order to do
-    // this, scratch space needs to be allocated with the hs_alloc_scratch
-    // function. In typical usage, you would reuse this scratch space for many
-    // calls to hs_scan, but as we're only doing one, we'll be allocating it
-    // and deallocating it as soon as our matching is done.
+    // Finally, we feed the chunks to a stream with hs_scan_stream, which will
+    // search the input data for the pattern represented in the bytecode, and
+    // close the stream at the end of the file. The scratch space required by
+    // the scan is allocated once for the whole file.
     //
     // When matches occur, the specified callback function (eventHandler in
     // this file) will be called. Note that although it is reminiscent of
     // asynchronous APIs, Hyperscan operates synchronously: all matches will be
-    // found, and all callbacks issued, *before* hs_scan returns.
+    // found, and all callbacks issued, *before* hs_scan_stream returns.
     //
     // In this example, we provide the input pattern as the context pointer so
     // that the callback is able to print out the pattern that matched on each
     // match event.
     //
 
-    let scratch = match database.alloc() {
-        Ok(s) => s,
-        Err(err) => {
-            write!(io::stderr(),
-                   "ERROR: Unable to allocate scratch space. {}\n",
-                   err);
-            exit(-1);
-        }
-    };
-
-    println!("Scanning {} bytes with Hyperscan", input_data.len());
+    println!("Scanning `{}` with Hyperscan", input_filename);
 
     // This is the function that will be called for each match that occurs.
     fn event_handler(_: u32, _: u64, to: u64, _: u32, pattern: &hyperscan::Pattern) -> u32 {
@@ -128,14 +107,13 @@ fn main() {
         0
     };
 
-    if let Err(err) = database.scan(input_data.as_str(),
-                                    0,
-                                    &scratch,
-                                    Some(event_handler),
-                                    Some(&pattern)) {
-        write!(io::stderr(),
-               "ERROR: Unable to scan input buffer. Exiting. {}\n",
-               err);
-        exit(-1);
+    match database.scan_reader(input_file, BUF_SIZE, Some(event_handler), Some(&pattern)) {
+        Ok(bytes) => println!("Scanned {} bytes with Hyperscan", bytes),
+        Err(err) => {
+            write!(io::stderr(),
+                   "ERROR: Unable to scan input file. Exiting. {}\n",
+                   err);
+            exit(-1);
+        }
     }
 }
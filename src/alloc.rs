@@ -0,0 +1,324 @@
+use std::alloc::{GlobalAlloc, Layout};
+use std::ptr;
+use std::sync::{Arc, RwLock};
+
+use libc;
+
+use errors::Error;
+use memory::{self, MemoryCategory};
+use raw::*;
+
+/// A memory allocator used by Hyperscan.
+///
+/// Hyperscan frees the memory without its size, so the allocator must be able to
+/// find the size of an allocation from its address.
+pub trait Allocator: Send + Sync {
+    /// Allocate `size` bytes suitably aligned for any data type,
+    /// or returns a null pointer if the allocation failed.
+    unsafe fn alloc(&self, size: usize) -> *mut u8;
+
+    /// Free the memory previously allocated by `alloc`.
+    unsafe fn free(&self, ptr: *mut u8);
+}
+
+/// The system allocator, which calls `malloc()` and `free()` like Hyperscan by default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemAllocator;
+
+impl Allocator for SystemAllocator {
+    #[inline]
+    unsafe fn alloc(&self, size: usize) -> *mut u8 {
+        libc::malloc(size) as *mut u8
+    }
+
+    #[inline]
+    unsafe fn free(&self, ptr: *mut u8) {
+        libc::free(ptr as *mut libc::c_void)
+    }
+}
+
+/// The alignment of the memory allocated for Hyperscan.
+const ALIGNMENT: usize = 16;
+
+/// Adapt a Rust `GlobalAlloc` to an `Allocator`,
+/// which keeps the size of each allocation in a header before it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GlobalAllocator<A: GlobalAlloc>(pub A);
+
+impl<A: GlobalAlloc + Send + Sync> Allocator for GlobalAllocator<A> {
+    unsafe fn alloc(&self, size: usize) -> *mut u8 {
+        let layout = match size.checked_add(ALIGNMENT).map(|size| Layout::from_size_align(size, ALIGNMENT)) {
+            Some(Ok(layout)) => layout,
+            _ => return ptr::null_mut(),
+        };
+
+        let p = self.0.alloc(layout);
+
+        if p.is_null() {
+            return p;
+        }
+
+        *(p as *mut usize) = size;
+
+        p.offset(ALIGNMENT as isize)
+    }
+
+    unsafe fn free(&self, ptr: *mut u8) {
+        if ptr.is_null() {
+            return;
+        }
+
+        let p = ptr.offset(-(ALIGNMENT as isize));
+        let size = *(p as *const usize);
+
+        self.0
+            .dealloc(p, Layout::from_size_align_unchecked(size + ALIGNMENT, ALIGNMENT))
+    }
+}
+
+type AllocatorSlot = RwLock<Option<Arc<dyn Allocator>>>;
+
+static DATABASE_ALLOCATOR: AllocatorSlot = RwLock::new(None);
+static SCRATCH_ALLOCATOR: AllocatorSlot = RwLock::new(None);
+static STREAM_ALLOCATOR: AllocatorSlot = RwLock::new(None);
+static MISC_ALLOCATOR: AllocatorSlot = RwLock::new(None);
+
+unsafe fn alloc_from(slot: &AllocatorSlot, category: MemoryCategory, size: usize) -> *mut libc::c_void {
+    let p = {
+        let allocator = match slot.read() {
+            Ok(allocator) => allocator,
+            Err(err) => err.into_inner(),
+        };
+
+        match *allocator {
+            Some(ref allocator) => allocator.alloc(size) as *mut libc::c_void,
+            None => libc::malloc(size),
+        }
+    };
+
+    if !p.is_null() {
+        memory::on_alloc(category, p as usize, size);
+    }
+
+    p
+}
+
+unsafe fn free_from(slot: &AllocatorSlot, p: *mut libc::c_void) {
+    memory::on_free(p as usize);
+
+    let allocator = match slot.read() {
+        Ok(allocator) => allocator,
+        Err(err) => err.into_inner(),
+    };
+
+    match *allocator {
+        Some(ref allocator) => allocator.free(p as *mut u8),
+        None => libc::free(p),
+    }
+}
+
+macro_rules! allocator_hooks {
+    ($slot:ident, $category:expr, $alloc:ident, $free:ident) => {
+        unsafe extern "C" fn $alloc(size: usize) -> *mut libc::c_void {
+            alloc_from(&$slot, $category, size)
+        }
+
+        unsafe extern "C" fn $free(p: *mut libc::c_void) {
+            if !p.is_null() {
+                free_from(&$slot, p)
+            }
+        }
+    };
+}
+
+allocator_hooks!(DATABASE_ALLOCATOR, MemoryCategory::Database, database_alloc, database_free);
+allocator_hooks!(SCRATCH_ALLOCATOR, MemoryCategory::Scratch, scratch_alloc, scratch_free);
+allocator_hooks!(STREAM_ALLOCATOR, MemoryCategory::Stream, stream_alloc, stream_free);
+allocator_hooks!(MISC_ALLOCATOR, MemoryCategory::Misc, misc_alloc, misc_free);
+
+fn install(slot: &AllocatorSlot, allocator: Arc<dyn Allocator>) {
+    let mut current = match slot.write() {
+        Ok(current) => current,
+        Err(err) => err.into_inner(),
+    };
+
+    *current = Some(allocator);
+}
+
+/// Free the memory returned by the Hyperscan API (such as the database information and
+/// serialized databases) with the misc allocator.
+pub unsafe fn free_misc<T>(p: *mut T) {
+    misc_free(p as *mut libc::c_void)
+}
+
+/// Allocate the memory with the misc allocator.
+#[cfg(test)]
+pub unsafe fn alloc_misc<T>(size: usize) -> *mut T {
+    misc_alloc(size) as *mut T
+}
+
+/// Set the allocator used by Hyperscan for database bytecode, scratch space,
+/// stream state and the other data structures returned by the Hyperscan API.
+///
+/// # Safety
+///
+/// The allocator must be set before any memory is allocated by Hyperscan,
+/// since the memory will be freed with the current allocator, which can't free
+/// the memory allocated by `malloc()` or by the previous allocator.
+pub unsafe fn set_allocator<A: Allocator + 'static>(allocator: A) -> Result<(), Error> {
+    let allocator: Arc<dyn Allocator> = Arc::new(allocator);
+
+    install(&DATABASE_ALLOCATOR, allocator.clone());
+    install(&SCRATCH_ALLOCATOR, allocator.clone());
+    install(&STREAM_ALLOCATOR, allocator.clone());
+    install(&MISC_ALLOCATOR, allocator);
+
+    try!(set_hooks());
+
+    debug!("set allocator for all Hyperscan allocations");
+
+    Ok(())
+}
+
+/// Route all the Hyperscan allocations through the hooks, without changing the allocators.
+pub fn set_hooks() -> Result<(), Error> {
+    unsafe {
+        check_hs_error!(hs_set_database_allocator(Some(database_alloc), Some(database_free)));
+        check_hs_error!(hs_set_scratch_allocator(Some(scratch_alloc), Some(scratch_free)));
+        check_hs_error!(hs_set_stream_allocator(Some(stream_alloc), Some(stream_free)));
+        check_hs_error!(hs_set_misc_allocator(Some(misc_alloc), Some(misc_free)));
+    }
+
+    Ok(())
+}
+
+/// Set the allocator used by Hyperscan for database bytecode
+/// produced by the compile calls and the database deserialization.
+///
+/// # Safety
+///
+/// The allocator must be set before any database bytecode is allocated, see `set_allocator`.
+pub unsafe fn set_database_allocator<A: Allocator + 'static>(allocator: A) -> Result<(), Error> {
+    install(&DATABASE_ALLOCATOR, Arc::new(allocator));
+
+    check_hs_error!(hs_set_database_allocator(Some(database_alloc), Some(database_free)));
+
+    debug!("set allocator for Hyperscan databases");
+
+    Ok(())
+}
+
+/// Set the allocator used by Hyperscan for scratch space.
+///
+/// # Safety
+///
+/// The allocator must be set before any scratch space is allocated, see `set_allocator`.
+pub unsafe fn set_scratch_allocator<A: Allocator + 'static>(allocator: A) -> Result<(), Error> {
+    install(&SCRATCH_ALLOCATOR, Arc::new(allocator));
+
+    check_hs_error!(hs_set_scratch_allocator(Some(scratch_alloc), Some(scratch_free)));
+
+    debug!("set allocator for Hyperscan scratch space");
+
+    Ok(())
+}
+
+/// Set the allocator used by Hyperscan for stream state.
+///
+/// # Safety
+///
+/// The allocator must be set before any stream state is allocated, see `set_allocator`.
+pub unsafe fn set_stream_allocator<A: Allocator + 'static>(allocator: A) -> Result<(), Error> {
+    install(&STREAM_ALLOCATOR, Arc::new(allocator));
+
+    check_hs_error!(hs_set_stream_allocator(Some(stream_alloc), Some(stream_free)));
+
+    debug!("set allocator for Hyperscan streams");
+
+    Ok(())
+}
+
+/// Set the allocator used by Hyperscan for the items returned by the Hyperscan API,
+/// such as compile errors, expression information and serialized databases.
+///
+/// # Safety
+///
+/// The allocator must be set before any misc items is allocated, see `set_allocator`.
+pub unsafe fn set_misc_allocator<A: Allocator + 'static>(allocator: A) -> Result<(), Error> {
+    install(&MISC_ALLOCATOR, Arc::new(allocator));
+
+    check_hs_error!(hs_set_misc_allocator(Some(misc_alloc), Some(misc_free)));
+
+    debug!("set allocator for Hyperscan misc items");
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::alloc::System;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::super::*;
+    use super::*;
+
+    static SCRATCH_ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Count the allocations, and delegate them to `malloc()`,
+    /// so the memory allocated by the other tests can be freed with it.
+    struct CountingAllocator;
+
+    impl Allocator for CountingAllocator {
+        unsafe fn alloc(&self, size: usize) -> *mut u8 {
+            SCRATCH_ALLOCS.fetch_add(1, Ordering::SeqCst);
+
+            SystemAllocator.alloc(size)
+        }
+
+        unsafe fn free(&self, ptr: *mut u8) {
+            SystemAllocator.free(ptr)
+        }
+    }
+
+    #[test]
+    fn test_scratch_allocator() {
+        let _ = env_logger::try_init();
+
+        let previous = SCRATCH_ALLOCATOR.read().unwrap().clone();
+
+        unsafe { set_scratch_allocator(CountingAllocator).unwrap() };
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let allocs = SCRATCH_ALLOCS.load(Ordering::SeqCst);
+        let s = db.alloc().unwrap();
+
+        assert!(SCRATCH_ALLOCS.load(Ordering::SeqCst) > allocs);
+
+        db.scan::<()>("foo test bar", 0, &s, None, None).unwrap();
+
+        drop(s);
+
+        // restore the allocator for the other tests
+        *SCRATCH_ALLOCATOR.write().unwrap() = previous;
+    }
+
+    #[test]
+    fn test_global_allocator() {
+        let allocator = GlobalAllocator(System);
+
+        unsafe {
+            let p = allocator.alloc(100);
+
+            assert!(!p.is_null());
+            assert_eq!(p as usize % ALIGNMENT, 0);
+
+            ptr::write_bytes(p, 0xFF, 100);
+
+            allocator.free(p);
+
+            assert!(allocator.alloc(usize::max_value()).is_null());
+        }
+    }
+}
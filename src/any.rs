@@ -0,0 +1,257 @@
+use std::fmt;
+use std::ops::Deref;
+
+use api::*;
+use common::{BlockDatabase, RawSerializedDatabase, StreamingDatabase, VectoredDatabase};
+use constants::*;
+use errors::Error;
+
+/// A compiled pattern database whose mode is only known at runtime.
+#[derive(Debug)]
+pub enum AnyDatabase {
+    /// Block scan (non-streaming) database.
+    Block(BlockDatabase),
+    /// Streaming database.
+    Streaming(StreamingDatabase),
+    /// Vectored scanning database.
+    Vectored(VectoredDatabase),
+}
+
+macro_rules! with_database {
+    ($any:expr, $db:ident => $body:expr) => {
+        match $any {
+            AnyDatabase::Block(ref $db) => $body,
+            AnyDatabase::Streaming(ref $db) => $body,
+            AnyDatabase::Vectored(ref $db) => $body,
+        }
+    };
+}
+
+impl AnyDatabase {
+    /// Reconstruct a pattern database from a stream of bytes,
+    /// with the mode read from the serialized database.
+    pub fn deserialize(bytes: &[u8]) -> Result<AnyDatabase, Error> {
+        match try!(bytes.info()).mode {
+            HS_MODE_BLOCK => BlockDatabase::deserialize(bytes).map(AnyDatabase::Block),
+            HS_MODE_STREAM => StreamingDatabase::deserialize(bytes).map(AnyDatabase::Streaming),
+            HS_MODE_VECTORED => VectoredDatabase::deserialize(bytes).map(AnyDatabase::Vectored),
+            _ => Err(Error::DbModeError),
+        }
+    }
+
+    /// Serialize the pattern database to a stream of bytes.
+    pub fn serialize(&self) -> Result<AnySerializedDatabase, Error> {
+        let data = try!(with_database!(*self, db => db.serialize()));
+
+        Ok(AnySerializedDatabase {
+            mode: self.database_mode(),
+            data: SerializedData::Raw(data),
+        })
+    }
+
+    /// Returns a reference to the block database, or `None` if it is of another mode.
+    pub fn as_block(&self) -> Option<&BlockDatabase> {
+        match *self {
+            AnyDatabase::Block(ref db) => Some(db),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the streaming database, or `None` if it is of another mode.
+    pub fn as_streaming(&self) -> Option<&StreamingDatabase> {
+        match *self {
+            AnyDatabase::Streaming(ref db) => Some(db),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the vectored database, or `None` if it is of another mode.
+    pub fn as_vectored(&self) -> Option<&VectoredDatabase> {
+        match *self {
+            AnyDatabase::Vectored(ref db) => Some(db),
+            _ => None,
+        }
+    }
+
+    /// Convert into the block database, or returns itself if it is of another mode.
+    pub fn into_block(self) -> Result<BlockDatabase, AnyDatabase> {
+        match self {
+            AnyDatabase::Block(db) => Ok(db),
+            any => Err(any),
+        }
+    }
+
+    /// Convert into the streaming database, or returns itself if it is of another mode.
+    pub fn into_streaming(self) -> Result<StreamingDatabase, AnyDatabase> {
+        match self {
+            AnyDatabase::Streaming(db) => Ok(db),
+            any => Err(any),
+        }
+    }
+
+    /// Convert into the vectored database, or returns itself if it is of another mode.
+    pub fn into_vectored(self) -> Result<VectoredDatabase, AnyDatabase> {
+        match self {
+            AnyDatabase::Vectored(db) => Ok(db),
+            any => Err(any),
+        }
+    }
+}
+
+impl Deref for AnyDatabase {
+    type Target = RawDatabasePtr;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        with_database!(*self, db => db.deref())
+    }
+}
+
+impl Database for AnyDatabase {
+    fn database_mode(&self) -> u32 {
+        with_database!(*self, db => db.database_mode())
+    }
+
+    fn database_name(&self) -> &'static str {
+        with_database!(*self, db => db.database_name())
+    }
+
+    fn database_size(&self) -> Result<usize, Error> {
+        with_database!(*self, db => db.database_size())
+    }
+
+    fn database_info(&self) -> Result<String, Error> {
+        with_database!(*self, db => db.database_info())
+    }
+}
+
+impl From<BlockDatabase> for AnyDatabase {
+    fn from(db: BlockDatabase) -> AnyDatabase {
+        AnyDatabase::Block(db)
+    }
+}
+
+impl From<StreamingDatabase> for AnyDatabase {
+    fn from(db: StreamingDatabase) -> AnyDatabase {
+        AnyDatabase::Streaming(db)
+    }
+}
+
+impl From<VectoredDatabase> for AnyDatabase {
+    fn from(db: VectoredDatabase) -> AnyDatabase {
+        AnyDatabase::Vectored(db)
+    }
+}
+
+enum SerializedData {
+    /// Serialized by the Hyperscan library.
+    Raw(RawSerializedDatabase),
+    /// Loaded from somewhere else, such as a file.
+    Owned(Vec<u8>),
+}
+
+/// A serialized pattern database whose mode is only known at runtime.
+pub struct AnySerializedDatabase {
+    mode: u32,
+    data: SerializedData,
+}
+
+impl fmt::Debug for AnySerializedDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AnySerializedDatabase{{mode: {}, len: {}}}", self.mode, self.len())
+    }
+}
+
+impl AnySerializedDatabase {
+    /// Take the serialized database, with the mode read from it.
+    pub fn new(bytes: Vec<u8>) -> Result<AnySerializedDatabase, Error> {
+        let mode = try!(bytes.as_slice().info()).mode;
+
+        Ok(AnySerializedDatabase {
+            mode: mode,
+            data: SerializedData::Owned(bytes),
+        })
+    }
+
+    /// The mode of the serialized database.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// Reconstruct the pattern database of the serialized mode.
+    pub fn deserialize_any(&self) -> Result<AnyDatabase, Error> {
+        AnyDatabase::deserialize(self.as_slice())
+    }
+
+    /// Returns the serialized bytes.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.data {
+            SerializedData::Raw(data) => data.as_slice().to_vec(),
+            SerializedData::Owned(bytes) => bytes,
+        }
+    }
+}
+
+impl SerializedDatabase for AnySerializedDatabase {
+    fn len(&self) -> usize {
+        match self.data {
+            SerializedData::Raw(ref data) => data.len(),
+            SerializedData::Owned(ref bytes) => bytes.len(),
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        match self.data {
+            SerializedData::Raw(ref data) => data.as_slice(),
+            SerializedData::Owned(ref bytes) => bytes.as_slice(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_any_database() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let data = db.serialize().unwrap();
+
+        let db = AnyDatabase::deserialize(data.as_slice()).unwrap();
+
+        assert_eq!(db.database_mode(), HS_MODE_STREAM);
+        assert_eq!(db.database_name(), "Streaming");
+        assert!(db.as_block().is_none());
+        assert!(db.as_streaming().is_some());
+
+        let db = db.into_block().unwrap_err();
+        let db = db.into_streaming().unwrap();
+
+        let s = db.alloc().unwrap();
+        let stream = db.open_stream(0).unwrap();
+
+        stream.scan::<()>("foo test bar", 0, &s, None, None).unwrap();
+        stream.close::<()>(&s, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_any_serialized_database() {
+        let _ = env_logger::try_init();
+
+        let db: AnyDatabase = BlockDatabase::compile("test", 0, &PlatformInfo::null()).unwrap().into();
+        let data = db.serialize().unwrap();
+
+        assert_eq!(data.mode(), HS_MODE_BLOCK);
+
+        let data = AnySerializedDatabase::new(data.into_vec()).unwrap();
+
+        assert_eq!(data.mode(), HS_MODE_BLOCK);
+        assert!(data.deserialize_any().unwrap().as_block().is_some());
+
+        assert!(AnySerializedDatabase::new(b"not a database".to_vec()).is_err());
+    }
+}
@@ -1,16 +1,18 @@
 use std::cell::RefCell;
 use std::ffi::CStr;
 use std::fmt;
+use std::io::IoSlice;
 use std::mem;
 use std::ops::Deref;
 use std::os::raw::c_char;
 use std::ptr;
+use std::str::FromStr;
 
-use libc;
-
+use alloc::free_misc;
 use constants::*;
 use errors::Error;
 use raw::*;
+use version::Version;
 
 /// Compile mode
 pub trait Type {
@@ -82,6 +84,11 @@ pub trait Database: Deref<Target = RawDatabasePtr> {
 
     /// Utility function providing information about a database.
     fn database_info(&self) -> Result<String, Error>;
+
+    /// Provides the parsed information about a database.
+    fn info(&self) -> Result<DatabaseInfo, Error> {
+        self.database_info().and_then(|info| info.parse())
+    }
 }
 
 /// A pattern database can be serialized to a stream of bytes.
@@ -137,11 +144,64 @@ pub trait SerializedDatabase {
                 Err(_) => Err(Error::Invalid),
             };
 
-            libc::free(p as *mut libc::c_void);
+            free_misc(p);
 
             result
         }
     }
+
+    /// Provides the parsed information about a serialized database.
+    fn info(&self) -> Result<DatabaseInfo, Error> {
+        self.database_info().and_then(|info| info.parse())
+    }
+}
+
+/// The information about a database, which is provided by the Hyperscan library,
+/// such as `Version: 5.0.0 Features: AVX2 Mode: STREAM`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseInfo {
+    /// The version of Hyperscan which has built the database.
+    pub version: Version,
+    /// The CPU features the database was built for, such as `AVX2`.
+    pub features: Option<String>,
+    /// The mode of the database, one of `HS_MODE_BLOCK`, `HS_MODE_STREAM` and `HS_MODE_VECTORED`.
+    pub mode: u32,
+}
+
+impl FromStr for DatabaseInfo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DatabaseInfo, Error> {
+        const VERSION: &str = "Version:";
+        const FEATURES: &str = "Features:";
+        const MODE: &str = "Mode:";
+
+        let (features_at, mode_at) = match (s.find(FEATURES), s.find(MODE)) {
+            (Some(features_at), Some(mode_at)) if s.starts_with(VERSION) && features_at < mode_at => {
+                (features_at, mode_at)
+            }
+            _ => return Err(Error::Invalid),
+        };
+
+        let version = try!(s[VERSION.len()..features_at].parse());
+        let features = s[features_at + FEATURES.len()..mode_at].trim();
+        let mode = match s[mode_at + MODE.len()..].trim() {
+            "BLOCK" => HS_MODE_BLOCK,
+            "STREAM" => HS_MODE_STREAM,
+            "VECTORED" => HS_MODE_VECTORED,
+            _ => return Err(Error::Invalid),
+        };
+
+        Ok(DatabaseInfo {
+            version: version,
+            features: if features.is_empty() {
+                None
+            } else {
+                Some(features.to_owned())
+            },
+            mode: mode,
+        })
+    }
 }
 
 /// A type containing information on the target platform
@@ -185,6 +245,22 @@ impl PlatformInfo {
         })))
     }
 
+    /// The CPU family the database is tuned for, the null platform targets the host.
+    pub fn tune(&self) -> u32 {
+        match self.0 {
+            Some(ref info) => info.borrow().tune,
+            None => PlatformInfo::host().tune(),
+        }
+    }
+
+    /// The CPU features the database may use, the null platform targets the host.
+    pub fn cpu_features(&self) -> u64 {
+        match self.0 {
+            Some(ref info) => info.borrow().cpu_features,
+            None => PlatformInfo::host().cpu_features(),
+        }
+    }
+
     pub fn as_ptr(&self) -> RawPlatformInfoPtr {
         match self.0 {
             Some(ref info) => &*info.borrow(),
@@ -287,6 +363,12 @@ impl<'a> Scannable for &'a Vec<u8> {
         &self
     }
 }
+impl<'a> Scannable for IoSlice<'a> {
+    #[inline]
+    fn as_bytes(&self) -> &[u8] {
+        &self
+    }
+}
 
 /// Flags modifying the behaviour of scan function
 pub type ScanFlags = u32;
@@ -348,9 +430,12 @@ pub trait BlockScanner<T: Scannable, S: Scratch> {
 pub trait VectoredScanner<T: Scannable, S: Scratch> {
     /// This is the function call in which the actual pattern matching
     /// takes place for vectoring-mode pattern databases.
+    ///
+    /// The match offsets are reported in the virtual concatenation of the segments,
+    /// use `Segments` to resolve them to the segment relative offsets.
     fn scan<D>(
         &self,
-        data: &Vec<T>,
+        data: &[T],
         flags: ScanFlags,
         scratch: &S,
         callback: Option<MatchEventCallback<D>>,
@@ -365,19 +450,31 @@ pub type RawStreamPtr = *mut hs_stream_t;
 pub type StreamFlags = u32;
 
 /// The stream returned by StreamingDatabase::open_stream
+///
+/// The stream state is freed when the stream is dropped,
+/// without reporting any end-of-data matches.
 pub trait Stream<S: Scratch>: Deref<Target = RawStreamPtr> {
-    /// Close a stream.
-    fn close<D>(
+    /// Close a stream, reporting any end-of-data (EOD) matches to the callback.
+    ///
+    /// The stream is consumed and can't be used again.
+    fn close<D>(self, scratch: &S, callback: Option<MatchEventCallback<D>>, context: Option<&D>) -> Result<(), Error>;
+
+    /// Reset a stream to an initial state.
+    fn reset<D>(
         &self,
+        flags: StreamFlags,
         scratch: &S,
         callback: Option<MatchEventCallback<D>>,
         context: Option<&D>,
     ) -> Result<&Self, Error>;
 
-    /// Reset a stream to an initial state.
-    fn reset<D>(
+    /// Duplicate the given `from` stream state onto this stream.
+    ///
+    /// This stream will first be reset, reporting any EOD matches to the callback,
+    /// which allows the stream state allocation to be reused.
+    fn reset_and_copy<D>(
         &self,
-        flags: StreamFlags,
+        from: &Self,
         scratch: &S,
         callback: Option<MatchEventCallback<D>>,
         context: Option<&D>,
@@ -0,0 +1,398 @@
+use std::cmp;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use api::*;
+use common::StreamingDatabase;
+use errors::{Error, ScanError};
+use runtime::{RawScratch, RawStream};
+
+/// The default maximum number of bytes scanned in one poll.
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+fn scan_error(err: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+/// An `AsyncRead`/`AsyncWrite` adapter which scans the bytes passing through in a stream.
+///
+/// At most `chunk_size` bytes are read or written and scanned in one poll,
+/// so that large buffers don't block the reactor.
+///
+/// The stream is closed, reporting any end-of-data (EOD) matches,
+/// when the inner reader reaches EOF, the writer is shutdown or `finish` is called.
+///
+/// The written bytes are scanned after the inner writer accepts them, so a scan error,
+/// such as `Error::ScanTerminated` when the callback stops the scan, is reported
+/// by the next write, flush, shutdown or `finish`.
+pub struct AsyncStreamScanner<'a, T, D: 'a> {
+    inner: T,
+    stream: Option<RawStream>,
+    scratch: RawScratch,
+    callback: Option<MatchEventCallback<D>>,
+    context: Option<&'a D>,
+    chunk_size: usize,
+    /// The error of scanning the bytes already accepted by the inner writer.
+    error: Option<Error>,
+}
+
+impl<'a, T, D: 'a> fmt::Debug for AsyncStreamScanner<'a, T, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "AsyncStreamScanner{{stream: {:?}, scratch: {:?}, chunk_size: {}}}",
+            self.stream, self.scratch, self.chunk_size
+        )
+    }
+}
+
+impl<'a, T, D: 'a> AsyncStreamScanner<'a, T, D> {
+    /// Open a stream against the database to scan the bytes read from or written to `inner`.
+    pub fn new(
+        db: &StreamingDatabase,
+        inner: T,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&'a D>,
+    ) -> Result<AsyncStreamScanner<'a, T, D>, Error> {
+        Ok(AsyncStreamScanner {
+            inner: inner,
+            stream: Some(try!(db.open_stream(0))),
+            scratch: try!(db.alloc()),
+            callback: callback,
+            context: context,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            error: None,
+        })
+    }
+
+    /// Set the maximum number of bytes scanned in one poll.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) -> &mut Self {
+        self.chunk_size = cmp::max(chunk_size, 1);
+
+        self
+    }
+
+    /// Gets a reference to the inner reader or writer.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the inner reader or writer.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Close the stream, reporting any EOD matches, and return the inner reader or writer.
+    pub fn finish(mut self) -> Result<T, Error> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        try!(self.close_stream());
+
+        Ok(self.inner)
+    }
+
+    fn scan(&self, data: &[u8]) -> Result<(), Error> {
+        match self.stream {
+            Some(ref stream) => {
+                try!(stream.scan(data, 0, &self.scratch, self.callback, self.context));
+
+                Ok(())
+            }
+            None if data.is_empty() => Ok(()),
+            None => Err(Error::Invalid),
+        }
+    }
+
+    fn close_stream(&mut self) -> Result<(), Error> {
+        match self.stream.take() {
+            Some(stream) => stream.close(&self.scratch, self.callback, self.context),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, T: AsyncRead + Unpin, D: 'a> AsyncRead for AsyncStreamScanner<'a, T, D> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let n = {
+            let mut chunk = buf.take(this.chunk_size);
+
+            match Pin::new(&mut this.inner).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(())) => {}
+                other => return other,
+            }
+
+            let res = if chunk.filled().is_empty() {
+                this.close_stream()
+            } else {
+                this.scan(chunk.filled())
+            };
+
+            if let Err(err) = res {
+                return Poll::Ready(Err(scan_error(err)));
+            }
+
+            chunk.filled().len()
+        };
+
+        unsafe {
+            buf.assume_init(n);
+        }
+        buf.advance(n);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, T: AsyncWrite + Unpin, D: 'a> AsyncWrite for AsyncStreamScanner<'a, T, D> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if let Some(err) = this.error.take() {
+            return Poll::Ready(Err(scan_error(err)));
+        }
+
+        if this.stream.is_none() {
+            return Poll::Ready(Err(scan_error(Error::Invalid)));
+        }
+
+        let len = cmp::min(buf.len(), this.chunk_size);
+        let n = match Pin::new(&mut this.inner).poll_write(cx, &buf[..len]) {
+            Poll::Ready(Ok(n)) => n,
+            other => return other,
+        };
+
+        // the bytes were written, report the error with the next call
+        if let Err(err) = this.scan(&buf[..n]) {
+            this.error = Some(err);
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if let Some(err) = this.error.take() {
+            return Poll::Ready(Err(scan_error(err)));
+        }
+
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if let Some(err) = this.error.take() {
+            return Poll::Ready(Err(scan_error(err)));
+        }
+
+        if let Err(err) = this.close_stream() {
+            return Poll::Ready(Err(scan_error(err)));
+        }
+
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// A future which scans the data pulled from an `AsyncRead` source through a stream,
+/// created by `StreamingDatabase::scan_async_reader`.
+///
+/// It yields to the executor after each chunk, and resolves to the number of bytes scanned.
+pub struct ScanAsyncReader<'a, R, D: 'a> {
+    reader: R,
+    stream: Option<RawStream>,
+    scratch: RawScratch,
+    buf: Vec<u8>,
+    total: u64,
+    callback: Option<MatchEventCallback<D>>,
+    context: Option<&'a D>,
+}
+
+impl<'a, R, D: 'a> fmt::Debug for ScanAsyncReader<'a, R, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ScanAsyncReader{{stream: {:?}, scratch: {:?}, total: {}}}",
+            self.stream, self.scratch, self.total
+        )
+    }
+}
+
+impl<'a, R: AsyncRead + Unpin, D: 'a> Future for ScanAsyncReader<'a, R, D> {
+    type Output = Result<u64, ScanError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        let n = {
+            let mut buf = ReadBuf::new(&mut this.buf);
+
+            match Pin::new(&mut this.reader).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err.into())),
+                Poll::Ready(Ok(())) => buf.filled().len(),
+            }
+        };
+
+        let stream = match this.stream.take() {
+            Some(stream) => stream,
+            None => return Poll::Ready(Err(Error::Invalid.into())),
+        };
+
+        if n == 0 {
+            return Poll::Ready(
+                stream
+                    .close(&this.scratch, this.callback, this.context)
+                    .map(|_| this.total)
+                    .map_err(|err| err.into()),
+            );
+        }
+
+        if let Err(err) = stream.scan(&this.buf[..n], 0, &this.scratch, this.callback, this.context) {
+            return Poll::Ready(Err(err.into()));
+        }
+
+        this.stream = Some(stream);
+        this.total += n as u64;
+
+        // yield to the executor after each chunk, to avoid blocking the reactor
+        cx.waker().wake_by_ref();
+
+        Poll::Pending
+    }
+}
+
+impl StreamingDatabase {
+    /// Scan the data pulled from the async reader in chunks of `buf_size` bytes through a stream,
+    /// and close the stream at the end of the data.
+    ///
+    /// The match offsets are absolute positions from the start of the reader.
+    pub fn scan_async_reader<'a, R: AsyncRead + Unpin, D: 'a>(
+        &self,
+        reader: R,
+        buf_size: usize,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&'a D>,
+    ) -> Result<ScanAsyncReader<'a, R, D>, Error> {
+        if buf_size == 0 {
+            return Err(Error::Invalid);
+        }
+
+        Ok(ScanAsyncReader {
+            reader: reader,
+            stream: Some(try!(self.open_stream(0))),
+            scratch: try!(self.alloc()),
+            buf: vec![0; buf_size],
+            total: 0,
+            callback: callback,
+            context: context,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+    use std::sync::Mutex;
+
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+    use tokio::runtime::{Builder, Runtime};
+
+    use super::super::*;
+    use super::super::harness::{on_match, stop_on_match};
+
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().build().unwrap()
+    }
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    #[test]
+    fn test_scan_async_reader() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = patterns!(["test", "bar$"]).build().unwrap();
+        let rt = runtime();
+
+        let (mut client, server) = duplex(1024);
+
+        rt.block_on(client.write_all(b"foo test bar test bar")).unwrap();
+
+        drop(client);
+
+        let matches = RefCell::new(Vec::new());
+        let fut = db.scan_async_reader(server, 4, Some(on_match), Some(&matches))
+            .unwrap();
+
+        assert_eq!(rt.block_on(fut).unwrap(), 21);
+        assert_eq!(*matches.borrow(), vec![(1, 8), (1, 17), (2, 21)]);
+
+        let matches = Mutex::new(0);
+        let fut = db.scan_async_reader::<_, Mutex<usize>>(&b"test"[..], 4, None, Some(&matches))
+            .unwrap();
+
+        assert_send(&fut);
+    }
+
+    #[test]
+    fn test_async_stream_scanner() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = patterns!(["test", "bar$"]).build().unwrap();
+        let rt = runtime();
+
+        let matches = RefCell::new(Vec::new());
+        let mut w = AsyncStreamScanner::new(&db, Vec::new(), Some(on_match), Some(&matches)).unwrap();
+
+        w.set_chunk_size(3);
+
+        rt.block_on(w.write_all(b"foo test bar")).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(1, 8)]);
+
+        rt.block_on(w.shutdown()).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(1, 8), (2, 12)]);
+        assert_eq!(w.get_ref(), b"foo test bar");
+
+        let matches = RefCell::new(Vec::new());
+        let mut r = AsyncStreamScanner::new(&db, &b"foo test bar"[..], Some(on_match), Some(&matches)).unwrap();
+        let mut data = Vec::new();
+
+        rt.block_on(r.read_to_end(&mut data)).unwrap();
+
+        assert_eq!(data, b"foo test bar");
+        assert_eq!(*matches.borrow(), vec![(1, 8), (2, 12)]);
+    }
+
+    #[test]
+    fn test_async_stream_scanner_terminated() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let rt = runtime();
+
+        let mut w = AsyncStreamScanner::new(&db, Vec::new(), Some(stop_on_match), Some(&())).unwrap();
+
+        w.set_chunk_size(3);
+
+        // the chunk completing the match is accepted, the next write reports the error
+        assert!(rt.block_on(w.write_all(b"foo test bar")).is_err());
+        assert_eq!(w.get_ref(), b"foo test ");
+    }
+}
@@ -0,0 +1,418 @@
+// hsc: the Hyperscan database compiler
+//
+// Compile a pattern file, one `id:/expression/flags` per line, into a serialized
+// database of the mode for the target platform, and report the information
+// of the database and each pattern. It can also validate the serialized
+// databases (or the sealed envelopes of them) before they are released.
+//
+// Build instructions:
+//
+//     cargo build --features cli --bin hsc
+//
+// Usage:
+//
+//     hsc [options] <pattern file>
+//     hsc --validate [options] <database file>...
+//
+// Example:
+//
+//     hsc --mode stream --tune hsw --cpu-features avx2 -o rules.db rules.txt
+//     hsc --validate --json rules.db
+//
+// With `--json` one JSON object is printed per input file, one per line.
+//
+// Exit status:
+//
+//     0   all the inputs have been compiled or validated
+//     1   the patterns failed to compile, or a database is invalid
+//     2   invalid command line arguments
+//     3   an input or output file can't be read or written
+//
+
+extern crate getopts;
+extern crate hyperscan;
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::process::exit;
+
+use getopts::Options;
+
+use hyperscan::*;
+
+const EXIT_INVALID: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_IO: i32 = 3;
+
+const TUNE_FAMILIES: [(&str, u32); 9] = [
+    ("generic", HS_TUNE_FAMILY_GENERIC),
+    ("snb", HS_TUNE_FAMILY_SNB),
+    ("ivb", HS_TUNE_FAMILY_IVB),
+    ("hsw", HS_TUNE_FAMILY_HSW),
+    ("slm", HS_TUNE_FAMILY_SLM),
+    ("bdw", HS_TUNE_FAMILY_BDW),
+    ("skl", HS_TUNE_FAMILY_SKL),
+    ("skx", HS_TUNE_FAMILY_SKX),
+    ("glm", HS_TUNE_FAMILY_GLM),
+];
+
+const CPU_FEATURES: [(&str, u32); 2] = [("avx2", HS_CPU_FEATURES_AVX2), ("avx512", HS_CPU_FEATURES_AVX512)];
+
+enum Failure {
+    Usage(String),
+    Io(String, io::Error),
+    Invalid(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> i32 {
+        match *self {
+            Failure::Usage(_) => EXIT_USAGE,
+            Failure::Io(..) => EXIT_IO,
+            Failure::Invalid(_) => EXIT_INVALID,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Failure::Usage(ref msg) | Failure::Invalid(ref msg) => write!(f, "{}", msg),
+            Failure::Io(ref path, ref err) => write!(f, "`{}`: {}", path, err),
+        }
+    }
+}
+
+/// The information of a compiled or validated database.
+struct Report {
+    mode: u32,
+    info: String,
+    database_size: usize,
+    serialized_size: usize,
+    stream_size: Option<usize>,
+    patterns: Vec<(Pattern, Result<ExpressionInfo, Error>)>,
+}
+
+impl Report {
+    fn new(db: &AnyDatabase, serialized_size: usize) -> Result<Report, Error> {
+        Ok(Report {
+            mode: db.database_mode(),
+            info: try!(db.database_info()),
+            database_size: try!(db.database_size()),
+            serialized_size: serialized_size,
+            stream_size: match db.as_streaming() {
+                Some(db) => Some(try!(db.stream_size())),
+                None => None,
+            },
+            patterns: Vec::new(),
+        })
+    }
+
+    fn print_text(&self, file: &str) {
+        println!("{}:", file);
+        println!("  mode: {}", mode_name(self.mode));
+        println!("  info: {}", self.info);
+        println!("  database size: {}", self.database_size);
+        println!("  serialized size: {}", self.serialized_size);
+
+        if let Some(size) = self.stream_size {
+            println!("  stream size: {}", size);
+        }
+
+        for &(ref pattern, ref info) in &self.patterns {
+            match *info {
+                Ok(ref info) => println!(
+                    "  pattern {}: width {}..{}{}{}{}",
+                    pattern,
+                    info.min_width,
+                    max_width(info).map_or_else(|| "inf".to_owned(), |n| n.to_string()),
+                    if info.unordered_matches { ", unordered" } else { "" },
+                    if info.matches_at_eod { ", matches at EOD" } else { "" },
+                    if info.matches_only_at_eod {
+                        ", matches only at EOD"
+                    } else {
+                        ""
+                    }
+                ),
+                Err(ref err) => println!("  pattern {}: {}", pattern, err),
+            }
+        }
+    }
+
+    fn print_json(&self, file: &str) {
+        let patterns: Vec<String> = self
+            .patterns
+            .iter()
+            .map(|&(ref pattern, ref info)| {
+                let head = format!(
+                    "{{\"id\":{},\"expression\":{},\"flags\":{}",
+                    pattern.id,
+                    json_str(&pattern.expression),
+                    json_str(&pattern.flags.to_string())
+                );
+
+                match *info {
+                    Ok(ref info) => format!(
+                        "{},\"min_width\":{},\"max_width\":{},\"unordered_matches\":{},\
+                         \"matches_at_eod\":{},\"matches_only_at_eod\":{}}}",
+                        head,
+                        info.min_width,
+                        max_width(info).map_or_else(|| "null".to_owned(), |n| n.to_string()),
+                        info.unordered_matches,
+                        info.matches_at_eod,
+                        info.matches_only_at_eod
+                    ),
+                    Err(ref err) => format!("{},\"error\":{}}}", head, json_str(&err.to_string())),
+                }
+            })
+            .collect();
+
+        println!(
+            "{{\"file\":{},\"ok\":true,\"mode\":{},\"info\":{},\"database_size\":{},\"serialized_size\":{},\
+             \"stream_size\":{},\"patterns\":[{}]}}",
+            json_str(file),
+            json_str(mode_name(self.mode)),
+            json_str(&self.info),
+            self.database_size,
+            self.serialized_size,
+            self.stream_size.map_or_else(|| "null".to_owned(), |n| n.to_string()),
+            patterns.join(",")
+        );
+    }
+}
+
+/// The maximum width of the matches, `None` if unbounded.
+fn max_width(info: &ExpressionInfo) -> Option<usize> {
+    if info.max_width == u32::max_value() as usize {
+        None
+    } else {
+        Some(info.max_width)
+    }
+}
+
+fn mode_name(mode: u32) -> &'static str {
+    match mode {
+        HS_MODE_BLOCK => "block",
+        HS_MODE_STREAM => "stream",
+        HS_MODE_VECTORED => "vectored",
+        _ => "unknown",
+    }
+}
+
+fn json_str(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len() + 2);
+
+    buf.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+
+    buf.push('"');
+    buf
+}
+
+fn parse_mode(s: &str) -> Result<u32, Failure> {
+    match s {
+        "block" => Ok(HS_MODE_BLOCK),
+        "stream" => Ok(HS_MODE_STREAM),
+        "vectored" => Ok(HS_MODE_VECTORED),
+        _ => Err(Failure::Usage(format!("unknown mode `{}`", s))),
+    }
+}
+
+fn parse_platform(tune: Option<String>, cpu_features: Option<String>) -> Result<PlatformInfo, Failure> {
+    if tune.is_none() && cpu_features.is_none() {
+        return Ok(PlatformInfo::null());
+    }
+
+    // the option which isn't given defaults to the host
+    let host = PlatformInfo::host();
+
+    let tune = match tune {
+        Some(name) => match TUNE_FAMILIES.iter().find(|&&(family, _)| family == name) {
+            Some(&(_, tune)) => tune,
+            None => return Err(Failure::Usage(format!("unknown tune family `{}`", name))),
+        },
+        None => host.tune(),
+    };
+
+    let features = match cpu_features {
+        Some(names) => {
+            let mut features = 0;
+
+            for name in names.split(',').filter(|s| !s.is_empty()) {
+                match CPU_FEATURES.iter().find(|&&(feature, _)| feature == name) {
+                    Some(&(_, feature)) => features |= feature as u64,
+                    None => return Err(Failure::Usage(format!("unknown CPU feature `{}`", name))),
+                }
+            }
+
+            features
+        }
+        None => host.cpu_features(),
+    };
+
+    Ok(PlatformInfo::new(tune, features))
+}
+
+fn compile(file: &str, mode: u32, platform: &PlatformInfo, output: Option<&str>) -> Result<Report, Failure> {
+    let source = try!(fs::read_to_string(file).map_err(|err| Failure::Io(file.to_owned(), err)));
+    let patterns = try!(parse_patterns(&source).map_err(|err| Failure::Invalid(format!("invalid pattern, {}", err))));
+
+    let db = try!(match mode {
+        HS_MODE_BLOCK => patterns.build_for_platform(platform).map(AnyDatabase::Block),
+        HS_MODE_STREAM => patterns.build_for_platform(platform).map(AnyDatabase::Streaming),
+        _ => patterns.build_for_platform(platform).map(AnyDatabase::Vectored),
+    }
+    .map_err(|err| Failure::Invalid(format!("fail to compile, {}", err))));
+
+    let invalid = |err: Error| Failure::Invalid(err.to_string());
+    let data = try!(db.serialize().map_err(&invalid));
+
+    if let Some(path) = output {
+        try!(fs::write(path, data.as_slice()).map_err(|err| Failure::Io(path.to_owned(), err)));
+    }
+
+    let mut report = try!(Report::new(&db, data.as_slice().len()).map_err(&invalid));
+
+    report.patterns = patterns
+        .into_iter()
+        .map(|pattern| {
+            let info = pattern.info();
+
+            (pattern, info)
+        })
+        .collect();
+
+    Ok(report)
+}
+
+fn validate(file: &str) -> Result<Report, Failure> {
+    let bytes = try!(fs::read(file).map_err(|err| Failure::Io(file.to_owned(), err)));
+    let invalid = |err: Error| Failure::Invalid(format!("invalid database, {}", err));
+
+    // the signature of a signed envelope is not verified without the key
+    let payload = if DatabaseEnvelope::is_envelope(&bytes) {
+        try!(DatabaseEnvelope::open(&bytes).map_err(&invalid)).payload()
+    } else {
+        &bytes[..]
+    };
+
+    let db = try!(AnyDatabase::deserialize(payload).map_err(&invalid));
+
+    Report::new(&db, payload.len()).map_err(&invalid)
+}
+
+fn usage(program: &str, opts: &Options) -> String {
+    let brief = format!(
+        "Usage: {0} [options] <pattern file>\n       {0} --validate [options] <database file>...",
+        program
+    );
+
+    opts.usage(&brief)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+
+    opts.optopt(
+        "m",
+        "mode",
+        "the mode of the database (default: block)",
+        "block|stream|vectored",
+    );
+    opts.optopt(
+        "t",
+        "tune",
+        "the CPU family to tune for (default: host)",
+        "generic|snb|ivb|hsw|...",
+    );
+    opts.optopt(
+        "F",
+        "cpu-features",
+        "the CPU features to use (default: host)",
+        "avx2,avx512",
+    );
+    opts.optopt("o", "output", "write the serialized database to the file", "FILE");
+    opts.optflag("V", "validate", "validate the serialized databases");
+    opts.optflag("j", "json", "print the reports in JSON, one object per line");
+    opts.optflag("h", "help", "print this help menu");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, usage(&program, &opts));
+            exit(EXIT_USAGE);
+        }
+    };
+
+    if matches.opt_present("h") {
+        println!("{}", usage(&program, &opts));
+        return;
+    }
+
+    let json = matches.opt_present("j");
+    let validating = matches.opt_present("V");
+
+    let inputs = if validating {
+        matches.free.len() > 0
+    } else {
+        matches.free.len() == 1
+    };
+
+    if !inputs {
+        eprintln!("{}", usage(&program, &opts));
+        exit(EXIT_USAGE);
+    }
+
+    let results: Vec<(&String, Result<Report, Failure>)> = if validating {
+        matches.free.iter().map(|file| (file, validate(file))).collect()
+    } else {
+        let file = &matches.free[0];
+        let res = parse_mode(&matches.opt_str("m").unwrap_or_else(|| "block".to_owned())).and_then(|mode| {
+            parse_platform(matches.opt_str("t"), matches.opt_str("F"))
+                .and_then(|platform| compile(file, mode, &platform, matches.opt_str("o").as_ref().map(|s| s.as_str())))
+        });
+
+        vec![(file, res)]
+    };
+
+    let mut code = 0;
+
+    for (file, res) in results {
+        match res {
+            Ok(ref report) if json => report.print_json(file),
+            Ok(ref report) => report.print_text(file),
+            Err(ref err) => {
+                if json {
+                    println!(
+                        "{{\"file\":{},\"ok\":false,\"exit_code\":{},\"error\":{}}}",
+                        json_str(file),
+                        err.exit_code(),
+                        json_str(&err.to_string())
+                    );
+                } else {
+                    eprintln!("ERROR: {}: {}", file, err);
+                }
+
+                code = code.max(err.exit_code());
+            }
+        }
+    }
+
+    exit(code);
+}
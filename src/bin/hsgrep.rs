@@ -0,0 +1,908 @@
+// hsgrep: search the files for the lines matching any of the patterns with Hyperscan
+//
+// The files are memory-mapped and scanned in block mode by a pool of threads, each with
+// its own scratch space, and the directories are walked recursively, skipping the hidden
+// files and the files excluded by the `.gitignore`, `.ignore` and `--exclude` globs.
+// The standard input and the files larger than 4 GiB are scanned in streaming mode,
+// so the lines of the standard input are printed as soon as they are read.
+// The matches are highlighted only if Hyperscan can report their start.
+//
+// The pattern files are read like the `hsc` ones, one `id:/expression/flags` per line.
+//
+// Build instructions:
+//
+//     cargo build --features cli --bin hsgrep
+//
+// Usage:
+//
+//     hsgrep [options] <pattern> [<path>...]
+//     hsgrep [options] -e <pattern>... [<path>...]
+//     hsgrep [options] -f <pattern file> [<path>...]
+//
+// Example:
+//
+//     hsgrep -n -C 2 -e 'fn \w+' -e 'impl\b' src
+//     dmesg | hsgrep -i usb
+//
+// Exit status:
+//
+//     0   some lines have matched
+//     1   no lines have matched
+//     2   an error occurred
+//
+
+extern crate getopts;
+extern crate hyperscan;
+extern crate ignore;
+extern crate libc;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use getopts::Options;
+use ignore::overrides::OverrideBuilder;
+use ignore::{WalkBuilder, WalkState};
+
+use hyperscan::*;
+
+const EXIT_MATCH: i32 = 0;
+const EXIT_NO_MATCH: i32 = 1;
+const EXIT_ERROR: i32 = 2;
+
+/// The size of the chunks read from the standard input.
+const BUF_SIZE: usize = 64 * 1024;
+
+/// The number of leading bytes checked for a NUL byte to detect the binary files.
+const BINARY_PROBE_SIZE: usize = 8 * 1024;
+
+const STDIN_NAME: &str = "(standard input)";
+
+const COLOR_PATH: &str = "\x1b[35m";
+const COLOR_LINE_NUMBER: &str = "\x1b[32m";
+const COLOR_MATCH: &str = "\x1b[1;31m";
+const COLOR_SEPARATOR: &str = "\x1b[36m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    /// Print the matching lines with their context.
+    Lines,
+    /// Print the number of the matching lines of each file.
+    Count,
+    /// Print the names of the files with a matching line.
+    Files,
+}
+
+#[derive(Debug)]
+struct Config {
+    output: Output,
+    line_number: bool,
+    with_filename: bool,
+    before: usize,
+    after: usize,
+    color: bool,
+}
+
+/// A database, and whether it reports the start of the matches to highlight them.
+struct Compiled<T: Type> {
+    db: RawDatabase<T>,
+    som: bool,
+}
+
+/// Compile the patterns reporting the start of the matches if `som` is set,
+/// or without it if some pattern doesn't support it.
+fn compile<T: Type>(patterns: &Patterns, som: bool) -> Result<Compiled<T>, Error> {
+    if som {
+        let leftmost: Patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let mut pattern = pattern.clone();
+
+                pattern.flags.set(HS_FLAG_SOM_LEFTMOST);
+                pattern
+            })
+            .collect();
+
+        // Hyperscan can't report the start of the matches of some patterns, print them without highlighting
+        if let Ok(db) = leftmost.build() {
+            return Ok(Compiled { db: db, som: true });
+        }
+    }
+
+    patterns.build().map(|db| Compiled { db: db, som: false })
+}
+
+/// The streaming database compiled on the first use, to scan the standard input
+/// and the files too large to be scanned as a block.
+struct LazyStreaming<'a> {
+    patterns: &'a Patterns,
+    som: bool,
+    db: OnceLock<Result<Compiled<Streaming>, Error>>,
+}
+
+impl<'a> LazyStreaming<'a> {
+    fn new(patterns: &'a Patterns, som: bool) -> LazyStreaming<'a> {
+        LazyStreaming {
+            patterns: patterns,
+            som: som,
+            db: OnceLock::new(),
+        }
+    }
+
+    fn get(&self) -> Result<&Compiled<Streaming>, Error> {
+        self.db
+            .get_or_init(|| compile(self.patterns, self.som))
+            .as_ref()
+            .map_err(|err| err.clone())
+    }
+}
+
+/// Print the matching lines reported by the scan with their context.
+struct Printer<'a, W: Write> {
+    config: &'a Config,
+    name: &'a str,
+    out: W,
+    binary: bool,
+    /// Whether the matches are highlighted, only if the database reports their start.
+    highlight: bool,
+    /// The lines which may be printed as the context before the next matching line.
+    before: VecDeque<(u64, Vec<u8>)>,
+    /// The number of lines to print as the context after the last matching line.
+    after: usize,
+    /// The number of the last printed line, or 0.
+    last_printed: u64,
+    /// The number of the matching lines.
+    count: u64,
+    /// The error of writing the output, which stops the scan.
+    error: Option<io::Error>,
+}
+
+/// Print the line reported by the scan, and stop the scan on the output errors,
+/// or at the first matching line when only the names of the files are printed.
+fn on_line<W: Write>(line: &Line, printer: &RefCell<Printer<W>>) -> u32 {
+    let mut printer = printer.borrow_mut();
+
+    match printer.line(line) {
+        Ok(()) if printer.config.output == Output::Files && printer.count > 0 => 1,
+        Ok(()) => 0,
+        Err(err) => {
+            printer.error = Some(err);
+
+            1
+        }
+    }
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    fn new(config: &'a Config, name: &'a str, out: W) -> Printer<'a, W> {
+        Printer {
+            config: config,
+            name: name,
+            out: out,
+            binary: false,
+            highlight: config.color,
+            before: VecDeque::new(),
+            after: 0,
+            last_printed: 0,
+            count: 0,
+            error: None,
+        }
+    }
+
+    /// Print the summary of the file, returns the number of the matching lines.
+    fn finish(&mut self) -> io::Result<u64> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        match self.config.output {
+            Output::Count => {
+                if self.config.with_filename {
+                    try!(self.print_name(':'));
+                }
+
+                try!(writeln!(self.out, "{}", self.count));
+            }
+            Output::Files if self.count > 0 => {
+                try!(self.print_name('\n'));
+            }
+            Output::Lines if self.binary && self.count > 0 => {
+                try!(writeln!(self.out, "Binary file {} matches", self.name));
+            }
+            _ => {}
+        }
+
+        try!(self.out.flush());
+
+        Ok(self.count)
+    }
+
+    /// Print the line if it matches or is in the context of a matching line,
+    /// or keep it as the context before the next matching line.
+    fn line(&mut self, line: &Line) -> io::Result<()> {
+        let printing = self.config.output == Output::Lines && !self.binary;
+
+        if !line.matches.is_empty() {
+            self.count += 1;
+
+            if printing {
+                let first = self.before.front().map_or(line.number, |&(number, _)| number);
+
+                if (self.config.before > 0 || self.config.after > 0)
+                    && self.last_printed > 0
+                    && first > self.last_printed + 1
+                {
+                    try!(self.print_separator());
+                }
+
+                while let Some((number, before)) = self.before.pop_front() {
+                    try!(self.print_line(number, &before, '-', &[], 0));
+                }
+
+                try!(self.print_line(line.number, line.text, ':', line.matches, line.range.start));
+
+                self.after = self.config.after;
+            }
+        } else if printing {
+            if self.after > 0 {
+                try!(self.print_line(line.number, line.text, '-', &[], 0));
+
+                self.after -= 1;
+            } else if self.config.before > 0 {
+                if self.before.len() == self.config.before {
+                    self.before.pop_front();
+                }
+
+                self.before.push_back((line.number, line.text.to_vec()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn print_name(&mut self, sep: char) -> io::Result<()> {
+        if self.config.color {
+            write!(self.out, "{}{}{}{}", COLOR_PATH, self.name, COLOR_RESET, sep)
+        } else {
+            write!(self.out, "{}{}", self.name, sep)
+        }
+    }
+
+    fn print_separator(&mut self) -> io::Result<()> {
+        if self.config.color {
+            writeln!(self.out, "{}--{}", COLOR_SEPARATOR, COLOR_RESET)
+        } else {
+            writeln!(self.out, "--")
+        }
+    }
+
+    /// Print the line starting at the offset, highlighting the matches.
+    fn print_line(
+        &mut self,
+        number: u64,
+        text: &[u8],
+        sep: char,
+        matches: &[Match],
+        line_start: u64,
+    ) -> io::Result<()> {
+        if self.config.with_filename {
+            try!(self.print_name(sep));
+        }
+
+        if self.config.line_number {
+            if self.config.color {
+                try!(write!(
+                    self.out,
+                    "{}{}{}{}",
+                    COLOR_LINE_NUMBER, number, COLOR_RESET, sep
+                ));
+            } else {
+                try!(write!(self.out, "{}{}", number, sep));
+            }
+        }
+
+        let mut written = 0;
+
+        if self.highlight {
+            let mut ranges: Vec<(usize, usize)> = matches
+                .iter()
+                .map(|m| {
+                    (
+                        (m.from.max(line_start) - line_start) as usize,
+                        (m.to.min(line_start + text.len() as u64).max(line_start) - line_start) as usize,
+                    )
+                })
+                .filter(|&(from, to)| from < to)
+                .collect();
+
+            ranges.sort();
+
+            for (from, to) in ranges {
+                if to <= written {
+                    continue;
+                }
+
+                let from = from.max(written);
+
+                try!(self.out.write_all(&text[written..from]));
+                try!(write!(self.out, "{}", COLOR_MATCH));
+                try!(self.out.write_all(&text[from..to]));
+                try!(write!(self.out, "{}", COLOR_RESET));
+
+                written = to;
+            }
+        }
+
+        try!(self.out.write_all(&text[written..]));
+        try!(self.out.write_all(b"\n"));
+
+        self.last_printed = number;
+
+        Ok(())
+    }
+}
+
+fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_PROBE_SIZE)].contains(&0)
+}
+
+/// Whether the leading bytes of the file contain a NUL byte.
+fn probe_binary(path: &Path) -> io::Result<bool> {
+    let mut buf = Vec::with_capacity(BINARY_PROBE_SIZE);
+
+    try!(try!(File::open(path))
+        .take(BINARY_PROBE_SIZE as u64)
+        .read_to_end(&mut buf));
+
+    Ok(is_binary(&buf))
+}
+
+/// Memory-map the file and scan it as a block, or through a stream if it's larger than 4 GiB,
+/// returns the output and the number of the matching lines.
+fn search_file(
+    block: &Compiled<Block>,
+    scratch: &RawScratch,
+    streaming: &LazyStreaming,
+    path: &Path,
+    config: &Config,
+) -> Result<(Vec<u8>, u64), ScanError> {
+    let name = path.display().to_string();
+    let mut printer = Printer::new(config, &name, Vec::new());
+
+    printer.binary = try!(probe_binary(path));
+    printer.highlight &= block.som;
+
+    let printer = RefCell::new(printer);
+
+    match block.db.scan_file_each_line(path, scratch, on_line, &printer) {
+        Ok(_) | Err(ScanError::Scan(Error::ScanTerminated)) => {}
+        Err(ScanError::Scan(Error::TooLarge(_))) => {
+            let streaming = try!(streaming.get());
+
+            printer.borrow_mut().highlight = config.color && streaming.som;
+
+            let mut scanner = try!(LineScanner::each_line(&streaming.db, on_line, &printer));
+
+            match scanner.scan_file(path) {
+                Ok(_) | Err(ScanError::Scan(Error::ScanTerminated)) => {}
+                Err(err) => return Err(err),
+            }
+
+            match scanner.finish() {
+                Ok(_) | Err(Error::ScanTerminated) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Err(err) => return Err(err),
+    }
+
+    let mut printer = printer.into_inner();
+    let count = try!(printer.finish());
+
+    Ok((printer.out, count))
+}
+
+/// Scan the standard input through a stream, printing the lines as soon as they are completed.
+fn search_stdin(streaming: &LazyStreaming, config: &Config) -> Result<u64, ScanError> {
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let stdout = io::stdout();
+    let streaming = try!(streaming.get());
+    let mut printer = Printer::new(config, STDIN_NAME, BufWriter::new(stdout.lock()));
+
+    printer.highlight &= streaming.som;
+
+    let printer = RefCell::new(printer);
+    let mut scanner = try!(LineScanner::each_line(&streaming.db, on_line, &printer));
+    let mut buf = vec![0; BUF_SIZE];
+    let mut first = true;
+
+    loop {
+        let n = match stdin.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        if first {
+            printer.borrow_mut().binary = is_binary(&buf[..n]);
+            first = false;
+        }
+
+        match scanner.scan(&buf[..n]) {
+            Ok(_) => {}
+            Err(Error::ScanTerminated) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    match scanner.finish() {
+        Ok(_) | Err(Error::ScanTerminated) => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    let count = try!(printer.borrow_mut().finish());
+
+    Ok(count)
+}
+
+fn is_tty(fd: libc::c_int) -> bool {
+    unsafe { libc::isatty(fd) != 0 }
+}
+
+/// Read the patterns given with `-e`, or in the `-f` files, or else the first free argument,
+/// and add the flags to each of them.
+///
+/// The pattern files are parsed like the `hsc` ones, one `id:/expression/flags` per line,
+/// the lines without the slashes are taken as the expressions.
+fn read_patterns(opts: &getopts::Matches, free: &mut Vec<String>, flags: CompileFlags) -> Result<Patterns, String> {
+    let mut expressions = opts.opt_strs("e");
+
+    if expressions.is_empty() && !opts.opt_present("f") {
+        if free.is_empty() {
+            return Err("no pattern is given".to_owned());
+        }
+
+        expressions.push(free.remove(0));
+    }
+
+    let mut patterns: Patterns = expressions
+        .into_iter()
+        .enumerate()
+        .map(|(id, expression)| Pattern {
+            expression: expression,
+            flags: CompileFlags::default(),
+            id: id,
+        })
+        .collect();
+
+    for file in opts.opt_strs("f") {
+        let content = try!(fs::read_to_string(&file).map_err(|err| format!("{}: {}", file, err)));
+
+        patterns.extend(try!(
+            parse_patterns(&content).map_err(|err| format!("{}: {}", file, err))
+        ));
+    }
+
+    if patterns.is_empty() {
+        return Err("no pattern is given".to_owned());
+    }
+
+    for pattern in &mut patterns {
+        pattern.flags.set(flags.0);
+    }
+
+    Ok(patterns)
+}
+
+fn parse_context(opts: &getopts::Matches, name: &str) -> Result<usize, String> {
+    match opts.opt_str(name).or_else(|| opts.opt_str("C")) {
+        Some(n) => n.parse().map_err(|_| format!("invalid context length `{}`", n)),
+        None => Ok(0),
+    }
+}
+
+fn usage(program: &str, opts: &Options) -> String {
+    let brief = format!(
+        "Usage: {0} [options] <pattern> [<path>...]\n       {0} [options] -e <pattern>... [<path>...]",
+        program
+    );
+
+    opts.usage(&brief)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+
+    opts.optmulti("e", "regexp", "search for the pattern", "PATTERN");
+    opts.optmulti(
+        "f",
+        "file",
+        "search for the patterns in the file, one `id:/expression/flags` per line",
+        "FILE",
+    );
+    opts.optflag(
+        "i",
+        "ignore-case",
+        "match the patterns case insensitively (HS_FLAG_CASELESS)",
+    );
+    opts.optflag("s", "dotall", "let `.` match the newlines (HS_FLAG_DOTALL)");
+    opts.optflag(
+        "m",
+        "multiline",
+        "let `^` and `$` match at the newlines (HS_FLAG_MULTILINE)",
+    );
+    opts.optflag("n", "line-number", "print the line numbers");
+    opts.optopt("A", "after-context", "print NUM lines after each matching line", "NUM");
+    opts.optopt(
+        "B",
+        "before-context",
+        "print NUM lines before each matching line",
+        "NUM",
+    );
+    opts.optopt("C", "context", "print NUM lines around each matching line", "NUM");
+    opts.optflag("c", "count", "print the number of the matching lines of each file");
+    opts.optflag(
+        "l",
+        "files-with-matches",
+        "print the names of the files with a matching line",
+    );
+    opts.optflag("H", "with-filename", "print the file name of each matching line");
+    opts.optflag("", "no-filename", "don't print the file names");
+    opts.optopt(
+        "",
+        "color",
+        "highlight the matches (default: auto)",
+        "auto|always|never",
+    );
+    opts.optmulti(
+        "",
+        "exclude",
+        "skip the files and directories matching the glob",
+        "GLOB",
+    );
+    opts.optflag("", "hidden", "search the hidden files and directories");
+    opts.optflag("", "no-ignore", "don't respect the .gitignore and .ignore files");
+    opts.optopt(
+        "j",
+        "threads",
+        "the number of threads (default: the number of CPUs)",
+        "NUM",
+    );
+    opts.optflag("", "help", "print this help menu");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, usage(&program, &opts));
+            exit(EXIT_ERROR);
+        }
+    };
+
+    if matches.opt_present("help") {
+        println!("{}", usage(&program, &opts));
+        return;
+    }
+
+    let fail = |msg: String| -> ! {
+        eprintln!("{}: {}", program, msg);
+        exit(EXIT_ERROR);
+    };
+
+    let color = match matches.opt_str("color").as_deref() {
+        None | Some("auto") => is_tty(libc::STDOUT_FILENO),
+        Some("always") => true,
+        Some("never") => false,
+        Some(when) => fail(format!("invalid color mode `{}`", when)),
+    };
+
+    let mut flags = CompileFlags::default();
+
+    if matches.opt_present("i") {
+        flags.set(HS_FLAG_CASELESS);
+    }
+    if matches.opt_present("s") {
+        flags.set(HS_FLAG_DOTALL);
+    }
+    if matches.opt_present("m") {
+        flags.set(HS_FLAG_MULTILINE);
+    }
+
+    let mut free = matches.free.clone();
+    let patterns = read_patterns(&matches, &mut free, flags).unwrap_or_else(|err| fail(err));
+
+    if free.is_empty() {
+        free.push(if is_tty(libc::STDIN_FILENO) { "." } else { "-" }.to_owned());
+    }
+
+    let (stdin, paths): (Vec<_>, Vec<_>) = free.into_iter().partition(|path| path == "-");
+
+    let config = Config {
+        output: if matches.opt_present("l") {
+            Output::Files
+        } else if matches.opt_present("c") {
+            Output::Count
+        } else {
+            Output::Lines
+        },
+        line_number: matches.opt_present("n"),
+        with_filename: if matches.opt_present("no-filename") {
+            false
+        } else {
+            matches.opt_present("H")
+                || paths.len() + stdin.len() > 1
+                || paths.iter().any(|path| Path::new(path).is_dir())
+        },
+        before: parse_context(&matches, "B").unwrap_or_else(|err| fail(err)),
+        after: parse_context(&matches, "A").unwrap_or_else(|err| fail(err)),
+        color: color,
+    };
+
+    let matched = AtomicBool::new(false);
+    let errored = AtomicBool::new(false);
+    let streaming = LazyStreaming::new(&patterns, color);
+
+    if !stdin.is_empty() {
+        if let Err(err) = streaming.get() {
+            fail(format!("fail to compile patterns, {}", err));
+        }
+
+        match search_stdin(&streaming, &config) {
+            Ok(count) => {
+                if count > 0 {
+                    matched.store(true, Ordering::Relaxed);
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: {}: {}", program, STDIN_NAME, err);
+                errored.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    if !paths.is_empty() {
+        let block: Compiled<Block> =
+            compile(&patterns, color).unwrap_or_else(|err| fail(format!("fail to compile patterns, {}", err)));
+        let prototype = block
+            .db
+            .alloc()
+            .unwrap_or_else(|err| fail(format!("fail to allocate scratch, {}", err)));
+
+        let mut overrides = OverrideBuilder::new(".");
+
+        for glob in matches.opt_strs("exclude") {
+            if let Err(err) = overrides.add(&format!("!{}", glob)) {
+                fail(format!("invalid glob `{}`, {}", glob, err));
+            }
+        }
+
+        let overrides = overrides.build().unwrap_or_else(|err| fail(err.to_string()));
+        let threads = match matches.opt_str("j") {
+            Some(n) => n
+                .parse()
+                .unwrap_or_else(|_| fail(format!("invalid number of threads `{}`", n))),
+            None => 0,
+        };
+
+        let mut walker = WalkBuilder::new(&paths[0]);
+
+        for path in &paths[1..] {
+            walker.add(path);
+        }
+
+        walker
+            .standard_filters(!matches.opt_present("no-ignore"))
+            .hidden(!matches.opt_present("hidden"))
+            .overrides(overrides)
+            .threads(threads);
+
+        walker.build_parallel().run(|| {
+            // each thread scans with its own scratch space
+            let scratch = prototype.clone();
+            let (block, streaming, config) = (&block, &streaming, &config);
+            let (matched, errored, program) = (&matched, &errored, &program);
+
+            Box::new(move |entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        eprintln!("{}: {}", program, err);
+                        errored.store(true, Ordering::Relaxed);
+
+                        return WalkState::Continue;
+                    }
+                };
+
+                if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+                    return WalkState::Continue;
+                }
+
+                match search_file(block, &scratch, streaming, entry.path(), config) {
+                    Ok((out, count)) => {
+                        if count > 0 {
+                            matched.store(true, Ordering::Relaxed);
+                        }
+
+                        let stdout = io::stdout();
+                        let mut stdout = stdout.lock();
+
+                        if let Err(err) = stdout.write_all(&out) {
+                            if err.kind() == ErrorKind::BrokenPipe {
+                                return WalkState::Quit;
+                            }
+
+                            eprintln!("{}: {}", program, err);
+                            errored.store(true, Ordering::Relaxed);
+                        }
+                    }
+                    Err(err) => {
+                        eprintln!("{}: {}: {}", program, entry.path().display(), err);
+                        errored.store(true, Ordering::Relaxed);
+                    }
+                }
+
+                WalkState::Continue
+            })
+        });
+    }
+
+    exit(exit_status(
+        matched.load(Ordering::Relaxed),
+        errored.load(Ordering::Relaxed),
+    ));
+}
+
+/// An error takes precedence over the matches, like `grep`.
+fn exit_status(matched: bool, errored: bool) -> i32 {
+    if errored {
+        EXIT_ERROR
+    } else if matched {
+        EXIT_MATCH
+    } else {
+        EXIT_NO_MATCH
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    fn config(output: Output) -> Config {
+        Config {
+            output: output,
+            line_number: true,
+            with_filename: false,
+            before: 1,
+            after: 1,
+            color: false,
+        }
+    }
+
+    /// Print the lines of the text, the lines containing `x` match at each `x`.
+    fn print(config: &Config, text: &str) -> (String, Vec<u32>) {
+        let printer = RefCell::new(Printer::new(config, "test.txt", Vec::new()));
+        let mut offset = 0;
+        let mut results = Vec::new();
+
+        for (i, text) in text.split('\n').enumerate() {
+            let matches: Vec<Match> = text
+                .match_indices('x')
+                .map(|(off, _)| Match {
+                    id: 0,
+                    from: (offset + off) as u64,
+                    to: (offset + off + 1) as u64,
+                })
+                .collect();
+            let line = Line {
+                number: i as u64 + 1,
+                range: offset as u64..(offset + text.len()) as u64,
+                text: text.as_bytes(),
+                matches: &matches,
+            };
+
+            results.push(on_line(&line, &printer));
+
+            offset += text.len() + 1;
+        }
+
+        let mut printer = printer.into_inner();
+
+        printer.finish().unwrap();
+
+        (String::from_utf8(printer.out).unwrap(), results)
+    }
+
+    #[test]
+    fn test_print_lines() {
+        let (out, results) = print(&config(Output::Lines), "a\nbx\nc\nd\ne\nfx\ng");
+
+        assert_eq!(out, "1-a\n2:bx\n3-c\n--\n5-e\n6:fx\n7-g\n");
+        assert_eq!(results, vec![0; 7]);
+
+        let mut config = config(Output::Lines);
+
+        config.color = true;
+        config.line_number = false;
+        config.before = 0;
+        config.after = 0;
+
+        let (out, _) = print(&config, "a\naxbx\nc");
+
+        assert_eq!(out, "a\x1b[1;31mx\x1b[0mb\x1b[1;31mx\x1b[0m\n");
+    }
+
+    #[test]
+    fn test_print_summary() {
+        let (out, results) = print(&config(Output::Count), "x\ny\nx");
+
+        assert_eq!(out, "2\n");
+        assert_eq!(results, vec![0, 0, 0]);
+
+        // the scan stops at the first matching line
+        let (out, results) = print(&config(Output::Files), "y\nx\nx");
+
+        assert_eq!(out, "test.txt\n");
+        assert_eq!(results, vec![0, 1, 1]);
+
+        let lines = config(Output::Lines);
+        let mut printer = Printer::new(&lines, "test.bin", Vec::new());
+
+        printer.binary = true;
+        printer.count = 1;
+        printer.finish().unwrap();
+
+        assert_eq!(printer.out, b"Binary file test.bin matches\n");
+    }
+
+    #[test]
+    fn test_color_streaming() {
+        let patterns = vec![pattern! {"usb", flags => HS_FLAG_CASELESS}];
+        let streaming = LazyStreaming::new(&patterns, true);
+        let streaming = streaming.get().unwrap();
+
+        assert!(streaming.som);
+
+        let mut config = config(Output::Lines);
+
+        config.color = true;
+        config.line_number = false;
+        config.before = 0;
+        config.after = 0;
+
+        let printer = RefCell::new(Printer::new(&config, STDIN_NAME, Vec::new()));
+        let mut scanner = LineScanner::each_line(&streaming.db, on_line, &printer).unwrap();
+
+        scanner.scan(&b"hub\nnew US"[..]).unwrap();
+        scanner.scan(&b"B device\n"[..]).unwrap();
+        scanner.finish().unwrap();
+
+        let mut printer = printer.into_inner();
+
+        assert_eq!(printer.finish().unwrap(), 1);
+        assert_eq!(
+            String::from_utf8(printer.out).unwrap(),
+            "new \x1b[1;31mUSB\x1b[0m device\n"
+        );
+    }
+
+    #[test]
+    fn test_exit_status() {
+        assert_eq!(exit_status(true, false), EXIT_MATCH);
+        assert_eq!(exit_status(false, false), EXIT_NO_MATCH);
+        assert_eq!(exit_status(true, true), EXIT_ERROR);
+        assert_eq!(exit_status(false, true), EXIT_ERROR);
+    }
+}
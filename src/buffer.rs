@@ -0,0 +1,161 @@
+use std::alloc::{self, Layout};
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+
+use api::*;
+use common::{check_mode, RawDatabase};
+use errors::Error;
+use raw::*;
+
+/// The alignment of the database memory required by Hyperscan.
+const DATABASE_ALIGNMENT: usize = 8;
+
+/// An owned memory buffer with the alignment required by Hyperscan,
+/// which the serialized databases can be deserialized into without any allocation.
+pub struct DatabaseBuffer {
+    p: *mut u8,
+    layout: Layout,
+}
+
+impl fmt::Debug for DatabaseBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DatabaseBuffer{{p: {:p}, capacity: {}}}", self.p, self.capacity())
+    }
+}
+
+impl DatabaseBuffer {
+    /// Allocate a buffer of `capacity` bytes.
+    pub fn new(capacity: usize) -> Result<DatabaseBuffer, Error> {
+        let layout = try!(Layout::from_size_align(capacity.max(1), DATABASE_ALIGNMENT).map_err(|_| Error::Invalid));
+        let p = unsafe { alloc::alloc(layout) };
+
+        if p.is_null() {
+            return Err(Error::NoMem);
+        }
+
+        trace!("allocated database buffer {:p} of {} bytes", p, capacity);
+
+        Ok(DatabaseBuffer { p: p, layout: layout })
+    }
+
+    /// Allocate a buffer of exactly the size of the deserialized database.
+    pub fn for_serialized<S: SerializedDatabase + ?Sized>(data: &S) -> Result<DatabaseBuffer, Error> {
+        DatabaseBuffer::new(try!(data.database_size()))
+    }
+
+    /// The size of the buffer in bytes.
+    pub fn capacity(&self) -> usize {
+        self.layout.size()
+    }
+
+    /// Reconstruct a pattern database in the buffer from a stream of bytes
+    /// previously generated by `RawDatabase::serialize()`.
+    ///
+    /// The buffer can be reused to reload another database after the returned view is dropped.
+    /// Returns `Error::NoMem` if the deserialized database doesn't fit in the buffer.
+    pub fn deserialize<T: Type>(&mut self, bytes: &[u8]) -> Result<DatabaseRef<T>, Error> {
+        try!(check_mode::<T>(bytes));
+
+        let size = try!(bytes.database_size());
+
+        if size > self.capacity() {
+            debug!(
+                "{} database of {} bytes doesn't fit in buffer of {} bytes",
+                T::name(),
+                size,
+                self.capacity()
+            );
+
+            return Err(Error::NoMem);
+        }
+
+        unsafe {
+            check_hs_error!(hs_deserialize_database_at(
+                bytes.as_ptr() as *const i8,
+                bytes.len(),
+                self.p as *mut hs_database_t,
+            ));
+        }
+
+        debug!(
+            "deserialized {} database in buffer {:p} from {} bytes",
+            T::name(),
+            self.p,
+            bytes.len()
+        );
+
+        Ok(DatabaseRef {
+            db: ManuallyDrop::new(RawDatabase::from_raw(self.p as RawDatabasePtr)),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl Drop for DatabaseBuffer {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.p, self.layout) }
+    }
+}
+
+unsafe impl Send for DatabaseBuffer {}
+unsafe impl Sync for DatabaseBuffer {}
+
+/// A pattern database deserialized in a `DatabaseBuffer`, which can't outlive the buffer.
+pub struct DatabaseRef<'a, T: Type> {
+    db: ManuallyDrop<RawDatabase<T>>,
+    _marker: PhantomData<&'a mut DatabaseBuffer>,
+}
+
+impl<'a, T: Type> fmt::Debug for DatabaseRef<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DatabaseRef<{}>{{db: {:p}}}", T::name(), **self.db)
+    }
+}
+
+impl<'a, T: Type> Deref for DatabaseRef<'a, T> {
+    type Target = RawDatabase<T>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.db
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+
+    use super::super::*;
+    use super::super::harness::on_match;
+
+    #[test]
+    fn test_database_buffer() {
+        let _ = env_logger::try_init();
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let data = db.serialize().unwrap();
+        let mut buf = DatabaseBuffer::for_serialized(&data).unwrap();
+
+        assert_eq!(buf.capacity(), data.database_size().unwrap());
+
+        let matches = RefCell::new(Vec::new());
+
+        {
+            let db = buf.deserialize::<Block>(data.as_slice()).unwrap();
+            let s = db.alloc().unwrap();
+
+            db.scan("foo test bar", 0, &s, Some(on_match), Some(&matches)).unwrap();
+        }
+
+        assert_eq!(*matches.borrow(), vec![(0, 8)]);
+        assert_eq!(buf.deserialize::<Streaming>(data.as_slice()).err(), Some(Error::DbModeError));
+
+        let mut small = DatabaseBuffer::new(data.database_size().unwrap() - 1).unwrap();
+
+        assert_eq!(small.deserialize::<Block>(data.as_slice()).err(), Some(Error::NoMem));
+    }
+}
@@ -0,0 +1,388 @@
+use std::fs;
+use std::path::Path;
+use std::str;
+
+use api::*;
+use codec::{put_bytes, put_u32, put_u64, Reader};
+use common::{BlockDatabase, RawDatabase, StreamingDatabase, VectoredDatabase};
+use compile::{CompileFlags, Pattern, Patterns};
+use constants::*;
+use errors::{Error, ScanError};
+
+/// The magic number at the beginning of the bundle files.
+const BUNDLE_MAGIC: &[u8; 8] = b"HSBUNDLE";
+
+/// The version of the bundle file format.
+const BUNDLE_VERSION: u32 = 1;
+
+/// The modes which can be bundled.
+const BUNDLE_MODES: u32 = HS_MODE_BLOCK | HS_MODE_STREAM | HS_MODE_VECTORED;
+
+/// A pattern set compiled into the databases of several modes,
+/// which can be serialized together with the source patterns into one self-describing file.
+///
+/// The file starts with `HSBUNDLE` and the format version, followed by the patterns
+/// (the ID, flags and expression of each one), the content hash of the patterns,
+/// and the serialized database of each mode. All the integers are little-endian.
+#[derive(Debug)]
+pub struct DatabaseBundle {
+    patterns: Patterns,
+    hash: u64,
+    block: Option<BlockDatabase>,
+    streaming: Option<StreamingDatabase>,
+    vectored: Option<VectoredDatabase>,
+}
+
+impl DatabaseBundle {
+    /// Compile the patterns into the databases of the modes,
+    /// a combination of `HS_MODE_BLOCK`, `HS_MODE_STREAM` and `HS_MODE_VECTORED`.
+    pub fn build(patterns: Patterns, modes: u32) -> Result<DatabaseBundle, Error> {
+        DatabaseBundle::build_for_platform(patterns, modes, &PlatformInfo::null())
+    }
+
+    /// Compile the patterns into the databases of the modes for the platform.
+    pub fn build_for_platform(
+        patterns: Patterns,
+        modes: u32,
+        platform: &PlatformInfo,
+    ) -> Result<DatabaseBundle, Error> {
+        if modes == 0 || modes & !BUNDLE_MODES != 0 {
+            return Err(Error::Invalid);
+        }
+
+        let block = if modes & HS_MODE_BLOCK != 0 {
+            Some(try!(patterns.build_for_platform(platform)))
+        } else {
+            None
+        };
+        let streaming = if modes & HS_MODE_STREAM != 0 {
+            Some(try!(patterns.build_for_platform(platform)))
+        } else {
+            None
+        };
+        let vectored = if modes & HS_MODE_VECTORED != 0 {
+            Some(try!(patterns.build_for_platform(platform)))
+        } else {
+            None
+        };
+
+        debug!("bundled {} patterns in modes {:#x}", patterns.len(), modes);
+
+        Ok(DatabaseBundle {
+            hash: content_hash(&patterns),
+            patterns: patterns,
+            block: block,
+            streaming: streaming,
+            vectored: vectored,
+        })
+    }
+
+    /// The source patterns of the databases.
+    pub fn patterns(&self) -> &Patterns {
+        &self.patterns
+    }
+
+    /// The pattern of the ID reported to the match callback.
+    pub fn pattern(&self, id: usize) -> Option<&Pattern> {
+        self.patterns.iter().find(|pattern| pattern.id == id)
+    }
+
+    /// The content hash of the patterns.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// The modes of the bundled databases.
+    pub fn modes(&self) -> u32 {
+        let mut modes = 0;
+
+        if self.block.is_some() {
+            modes |= HS_MODE_BLOCK;
+        }
+        if self.streaming.is_some() {
+            modes |= HS_MODE_STREAM;
+        }
+        if self.vectored.is_some() {
+            modes |= HS_MODE_VECTORED;
+        }
+
+        modes
+    }
+
+    /// The block database, if bundled.
+    pub fn block(&self) -> Option<&BlockDatabase> {
+        self.block.as_ref()
+    }
+
+    /// The streaming database, if bundled.
+    pub fn streaming(&self) -> Option<&StreamingDatabase> {
+        self.streaming.as_ref()
+    }
+
+    /// The vectored database, if bundled.
+    pub fn vectored(&self) -> Option<&VectoredDatabase> {
+        self.vectored.as_ref()
+    }
+
+    /// Take the block database out of the bundle.
+    pub fn take_block(&mut self) -> Option<BlockDatabase> {
+        self.block.take()
+    }
+
+    /// Take the streaming database out of the bundle.
+    pub fn take_streaming(&mut self) -> Option<StreamingDatabase> {
+        self.streaming.take()
+    }
+
+    /// Take the vectored database out of the bundle.
+    pub fn take_vectored(&mut self) -> Option<VectoredDatabase> {
+        self.vectored.take()
+    }
+
+    /// Serialize the patterns and the databases into one stream of bytes.
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+
+        buf.extend_from_slice(BUNDLE_MAGIC);
+        put_u32(&mut buf, BUNDLE_VERSION);
+        put_u32(&mut buf, self.patterns.len() as u32);
+
+        for pattern in &self.patterns {
+            put_u32(&mut buf, pattern.id as u32);
+            put_u32(&mut buf, pattern.flags.0);
+            put_bytes(&mut buf, pattern.expression.as_bytes());
+        }
+
+        put_u64(&mut buf, self.hash);
+
+        let mut sections = Vec::new();
+
+        if let Some(ref db) = self.block {
+            sections.push((HS_MODE_BLOCK, try!(db.serialize())));
+        }
+        if let Some(ref db) = self.streaming {
+            sections.push((HS_MODE_STREAM, try!(db.serialize())));
+        }
+        if let Some(ref db) = self.vectored {
+            sections.push((HS_MODE_VECTORED, try!(db.serialize())));
+        }
+
+        put_u32(&mut buf, sections.len() as u32);
+
+        for (mode, data) in sections {
+            put_u32(&mut buf, mode);
+            put_bytes(&mut buf, data.as_slice());
+        }
+
+        debug!("serialized bundle of {} patterns to {} bytes", self.patterns.len(), buf.len());
+
+        Ok(buf)
+    }
+
+    /// Reconstruct the patterns and the databases from a stream of bytes
+    /// previously generated by `DatabaseBundle::serialize()`.
+    pub fn deserialize(bytes: &[u8]) -> Result<DatabaseBundle, Error> {
+        DatabaseBundle::deserialize_with(bytes, BUNDLE_MODES, false)
+    }
+
+    /// Reconstruct the patterns and the databases of the modes from a stream of bytes,
+    /// and compile the databases which can't be loaded from the source patterns,
+    /// such as the databases built for another platform or version of Hyperscan.
+    ///
+    /// The databases of the other modes are skipped, so they are neither loaded nor compiled.
+    pub fn deserialize_or_compile(bytes: &[u8], modes: u32) -> Result<DatabaseBundle, Error> {
+        DatabaseBundle::deserialize_with(bytes, modes, true)
+    }
+
+    fn deserialize_with(bytes: &[u8], modes: u32, fallback: bool) -> Result<DatabaseBundle, Error> {
+        let mut r = Reader(bytes);
+
+        if try!(r.take(BUNDLE_MAGIC.len())) != BUNDLE_MAGIC || try!(r.u32()) != BUNDLE_VERSION {
+            return Err(Error::Invalid);
+        }
+
+        let count = try!(r.u32()) as usize;
+        let mut patterns = Vec::with_capacity(count.min(r.remaining().len()));
+
+        for _ in 0..count {
+            let id = try!(r.u32()) as usize;
+            let flags = CompileFlags(try!(r.u32()));
+            let expression = try!(str::from_utf8(try!(r.bytes())).map_err(|_| Error::Invalid));
+
+            patterns.push(Pattern {
+                expression: expression.to_owned(),
+                flags: flags,
+                id: id,
+            });
+        }
+
+        let hash = try!(r.u64());
+
+        if hash != content_hash(&patterns) {
+            debug!("content hash {:#x} of bundle doesn't match its patterns", hash);
+
+            return Err(Error::Invalid);
+        }
+
+        let mut bundle = DatabaseBundle {
+            patterns: patterns,
+            hash: hash,
+            block: None,
+            streaming: None,
+            vectored: None,
+        };
+
+        for _ in 0..try!(r.u32()) {
+            let mode = try!(r.u32());
+            let data = try!(r.bytes());
+
+            match mode {
+                HS_MODE_BLOCK | HS_MODE_STREAM | HS_MODE_VECTORED if modes & mode == 0 => {
+                    trace!("skip {:#x} database of bundle", mode);
+                }
+                HS_MODE_BLOCK if bundle.block.is_none() => {
+                    bundle.block = Some(try!(load_or_compile(data, &bundle.patterns, fallback)))
+                }
+                HS_MODE_STREAM if bundle.streaming.is_none() => {
+                    bundle.streaming = Some(try!(load_or_compile(data, &bundle.patterns, fallback)))
+                }
+                HS_MODE_VECTORED if bundle.vectored.is_none() => {
+                    bundle.vectored = Some(try!(load_or_compile(data, &bundle.patterns, fallback)))
+                }
+                _ => return Err(Error::DbModeError),
+            }
+        }
+
+        if !r.remaining().is_empty() {
+            return Err(Error::Invalid);
+        }
+
+        debug!(
+            "deserialized bundle of {} patterns in modes {:#x} from {} bytes",
+            bundle.patterns.len(),
+            bundle.modes(),
+            bytes.len()
+        );
+
+        Ok(bundle)
+    }
+
+    /// Write the serialized bundle to the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ScanError> {
+        let bytes = try!(self.serialize());
+
+        Ok(try!(fs::write(path, bytes)))
+    }
+
+    /// Read the serialized bundle from the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<DatabaseBundle, ScanError> {
+        let bytes = try!(fs::read(path));
+
+        Ok(try!(DatabaseBundle::deserialize(&bytes)))
+    }
+}
+
+/// Deserialize the database, or compile it from the patterns if it can't be loaded and `fallback` is set.
+fn load_or_compile<T: Type>(data: &[u8], patterns: &Patterns, fallback: bool) -> Result<RawDatabase<T>, Error> {
+    match RawDatabase::deserialize(data) {
+        Err(err) if fallback => {
+            debug!("fail to load {} database from bundle, {}, compile it from patterns", T::name(), err);
+
+            patterns.build()
+        }
+        res => res,
+    }
+}
+
+/// The FNV-1a hash of the IDs, flags and expressions of the patterns.
+fn content_hash(patterns: &Patterns) -> u64 {
+    let mut buf = Vec::new();
+
+    for pattern in patterns {
+        put_u32(&mut buf, pattern.id as u32);
+        put_u32(&mut buf, pattern.flags.0);
+        put_bytes(&mut buf, pattern.expression.as_bytes());
+    }
+
+    buf.iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+
+    use super::super::*;
+    use super::super::harness::on_match;
+
+    #[test]
+    fn test_database_bundle() {
+        let _ = env_logger::try_init();
+
+        let patterns = patterns!(["test", "foo"], flags => HS_FLAG_CASELESS);
+        let bundle = DatabaseBundle::build(patterns, HS_MODE_BLOCK | HS_MODE_STREAM).unwrap();
+
+        assert_eq!(bundle.modes(), HS_MODE_BLOCK | HS_MODE_STREAM);
+        assert!(bundle.vectored().is_none());
+
+        let data = bundle.serialize().unwrap();
+        let mut bundle = DatabaseBundle::deserialize(&data).unwrap();
+
+        assert_eq!(bundle.modes(), HS_MODE_BLOCK | HS_MODE_STREAM);
+        assert_eq!(bundle.patterns().len(), 2);
+        assert_eq!(bundle.pattern(2).unwrap().expression, "foo");
+        assert_eq!(bundle.pattern(2).unwrap().flags, CompileFlags(HS_FLAG_CASELESS));
+
+        let matches = RefCell::new(Vec::new());
+        let db = bundle.take_block().unwrap();
+        let s = db.alloc().unwrap();
+
+        db.scan("FOO test", 0, &s, Some(on_match), Some(&matches)).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(2, 3), (1, 8)]);
+        assert_eq!(bundle.modes(), HS_MODE_STREAM);
+
+        assert_eq!(DatabaseBundle::deserialize(&data[..data.len() - 1]).err(), Some(Error::Invalid));
+    }
+
+    #[test]
+    fn test_bundle_compile_fallback() {
+        let _ = env_logger::try_init();
+
+        let bundle = DatabaseBundle::build(patterns!(["test"]), HS_MODE_STREAM).unwrap();
+        let mut data = bundle.serialize().unwrap();
+        let len = bundle.streaming().unwrap().serialize().unwrap().len();
+
+        // modify the version in the header of the serialized database, after its magic number
+        let off = data.len() - len + 4;
+
+        data[off] ^= 0xFF;
+
+        assert!(DatabaseBundle::deserialize(&data).is_err());
+
+        let bundle = DatabaseBundle::deserialize_or_compile(&data, HS_MODE_STREAM).unwrap();
+
+        assert_eq!(bundle.modes(), HS_MODE_STREAM);
+        assert!(bundle.streaming().is_some());
+
+        assert_eq!(DatabaseBundle::deserialize_or_compile(&data, HS_MODE_BLOCK).unwrap().modes(), 0);
+    }
+
+    #[test]
+    fn test_bundle_content_hash() {
+        let _ = env_logger::try_init();
+
+        let bundle = DatabaseBundle::build(patterns!(["test"]), HS_MODE_BLOCK).unwrap();
+        let mut data = bundle.serialize().unwrap();
+
+        // modify the expression of the pattern
+        let off = data.iter().position(|&b| b == b't').unwrap();
+
+        data[off] = b'b';
+
+        assert_eq!(DatabaseBundle::deserialize(&data).err(), Some(Error::Invalid));
+        assert_eq!(DatabaseBundle::build(patterns!(["test"]), 0).err(), Some(Error::Invalid));
+    }
+}
@@ -0,0 +1,64 @@
+use errors::Error;
+
+pub fn put_u32(buf: &mut Vec<u8>, n: u32) {
+    buf.extend_from_slice(&n.to_le_bytes());
+}
+
+pub fn put_u64(buf: &mut Vec<u8>, n: u64) {
+    buf.extend_from_slice(&n.to_le_bytes());
+}
+
+/// Put the bytes prefixed with their length.
+pub fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_u64(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Read the little-endian fields from the serialized files, such as the bundles and envelopes.
+pub struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// The bytes which haven't been read.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.0
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.0.len() {
+            return Err(Error::Invalid);
+        }
+
+        let (head, tail) = self.0.split_at(len);
+
+        self.0 = tail;
+
+        Ok(head)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        let mut n = [0; 4];
+
+        n.copy_from_slice(try!(self.take(4)));
+
+        Ok(u32::from_le_bytes(n))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        let mut n = [0; 8];
+
+        n.copy_from_slice(try!(self.take(8)));
+
+        Ok(u64::from_le_bytes(n))
+    }
+
+    /// Read the bytes prefixed with their length.
+    pub fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = try!(self.u64());
+
+        if len > self.0.len() as u64 {
+            return Err(Error::Invalid);
+        }
+
+        self.take(len as usize)
+    }
+}
@@ -6,8 +6,7 @@ use std::os::raw::c_char;
 use std::ptr;
 use std::slice;
 
-use libc;
-
+use alloc::free_misc;
 use api::*;
 use cptr::CPtr;
 use errors::Error;
@@ -100,7 +99,7 @@ impl<T: Type> Database for RawDatabase<T> {
 
             debug!("database info of {} database {:p}: {:?}", T::name(), self.db, result);
 
-            libc::free(p as *mut libc::c_void);
+            free_misc(p);
 
             result
         }
@@ -122,6 +121,8 @@ impl<T: Type> SerializableDatabase<RawDatabase<T>, RawSerializedDatabase> for Ra
     }
 
     fn deserialize(bytes: &[u8]) -> Result<RawDatabase<T>, Error> {
+        try!(check_mode::<T>(bytes));
+
         let mut db: RawDatabasePtr = ptr::null_mut();
 
         unsafe {
@@ -143,6 +144,8 @@ impl<T: Type> SerializableDatabase<RawDatabase<T>, RawSerializedDatabase> for Ra
     }
 
     fn deserialize_at(&self, bytes: &[u8]) -> Result<&RawDatabase<T>, Error> {
+        try!(check_mode::<T>(bytes));
+
         unsafe {
             check_hs_error!(hs_deserialize_database_at(
                 bytes.as_ptr() as *const i8,
@@ -162,6 +165,23 @@ impl<T: Type> SerializableDatabase<RawDatabase<T>, RawSerializedDatabase> for Ra
     }
 }
 
+/// Check that the bytes were serialized from a database of mode `T`.
+pub fn check_mode<T: Type>(bytes: &[u8]) -> Result<(), Error> {
+    let info = try!(bytes.info());
+
+    if info.mode != T::mode() {
+        debug!(
+            "reject to deserialize database of mode {} as {} database",
+            info.mode,
+            T::name()
+        );
+
+        return Err(Error::DbModeError);
+    }
+
+    Ok(())
+}
+
 unsafe impl<T: Type> Send for RawDatabase<T> {}
 unsafe impl<T: Type> Sync for RawDatabase<T> {}
 
@@ -173,6 +193,7 @@ impl<T: Type> Drop for RawDatabase<T> {
 }
 
 impl RawDatabase<Streaming> {
+    /// The size of the stream state allocated for each stream opened against the database.
     pub fn stream_size(&self) -> Result<usize, Error> {
         let mut size: usize = 0;
 
@@ -342,6 +363,42 @@ pub mod tests {
         validate_database(&db);
     }
 
+    #[test]
+    fn test_database_info() {
+        let _ = env_logger::try_init();
+
+        let info: DatabaseInfo = "Version: 5.0.0 Features: AVX2 Mode: STREAM".parse().unwrap();
+
+        assert_eq!(info.version, Version::new(5, 0, 0));
+        assert_eq!(info.features, Some("AVX2".to_owned()));
+        assert_eq!(info.mode, HS_MODE_STREAM);
+
+        let info: DatabaseInfo = "Version: 5.0.0 Features:  Mode: BLOCK".parse().unwrap();
+
+        assert_eq!(info.features, None);
+        assert_eq!(info.mode, HS_MODE_BLOCK);
+
+        assert!("Version: 5.0.0 Mode: BLOCK".parse::<DatabaseInfo>().is_err());
+
+        let db = VectoredDatabase::compile("test", 0, &PlatformInfo::null()).unwrap();
+        let info = db.info().unwrap();
+
+        assert_eq!(info.mode, HS_MODE_VECTORED);
+        assert_eq!(info, db.serialize().unwrap().info().unwrap());
+    }
+
+    #[test]
+    fn test_database_deserialize_mode_mismatch() {
+        let _ = env_logger::try_init();
+
+        let db = StreamingDatabase::compile("test", 0, &PlatformInfo::null()).unwrap();
+
+        let data = db.serialize().unwrap();
+
+        assert_eq!(BlockDatabase::deserialize(data.as_slice()).err(), Some(Error::DbModeError));
+        assert!(StreamingDatabase::deserialize(data.as_slice()).is_ok());
+    }
+
     #[test]
     fn test_database_deserialize_at() {
         let _ = env_logger::try_init();
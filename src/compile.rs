@@ -243,6 +243,18 @@ macro_rules! patterns {
     }};
 }
 
+/// The mode to compile the expressions with the flags in.
+///
+/// Hyperscan requires a SOM horizon to report the start of the matches in streaming mode,
+/// the largest one is used so the start is always exact.
+fn compile_mode<T: Type>(flags: &[u32]) -> u32 {
+    if T::mode() == HS_MODE_STREAM && flags.iter().any(|&flags| flags & HS_FLAG_SOM_LEFTMOST != 0) {
+        T::mode() | HS_MODE_SOM_HORIZON_LARGE
+    } else {
+        T::mode()
+    }
+}
+
 impl<T: Type> RawDatabase<T> {
     /// The basic regular expression compiler.
     ///
@@ -258,7 +270,7 @@ impl<T: Type> RawDatabase<T> {
                 hs_compile(
                     expr.as_bytes_with_nul().as_ptr() as *const i8,
                     flags,
-                    T::mode(),
+                    compile_mode::<T>(&[flags]),
                     platform.as_ptr(),
                     &mut db,
                     &mut err
@@ -328,7 +340,7 @@ impl<T: Type> DatabaseBuilder<RawDatabase<T>> for Patterns {
                     flags.as_ptr(),
                     ids.as_ptr(),
                     self.len() as u32,
-                    T::mode(),
+                    compile_mode::<T>(&flags),
                     platform.as_ptr(),
                     &mut db,
                     &mut err
@@ -489,4 +501,19 @@ pub mod tests {
 
         validate_database_with_size(&db, DATABASE_SIZE);
     }
+
+    #[test]
+    fn test_patterns_build_streaming_with_som() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = patterns!(["test", "foo"], flags => HS_FLAG_SOM_LEFTMOST)
+            .build()
+            .unwrap();
+
+        validate_database(&db);
+
+        let db: StreamingDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+
+        validate_database(&db);
+    }
 }
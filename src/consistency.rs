@@ -0,0 +1,260 @@
+use std::fmt;
+
+use compile::Patterns;
+use constants::*;
+use errors::Error;
+use harness::{self, mode_name, Matches, Rng};
+
+/// The number of the random segmentations and chunkings of the data.
+const RANDOM_SPLITS: usize = 32;
+
+/// The maximum number of the offsets to split the data at randomly.
+const MAX_RANDOM_CUTS: usize = 16;
+
+/// The data up to this size is also split into the chunks of one byte, and in two at each offset.
+const EXHAUSTIVE_LEN: usize = 256;
+
+/// The limits of the parts which the wrapper splits the too large segments and chunks into.
+const LIMITS: [usize; 3] = [1, 3, 7];
+
+/// The difference between the matches of a scan and the block mode scan of the same data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inconsistency {
+    /// The mode of the scan, `HS_MODE_STREAM` or `HS_MODE_VECTORED`.
+    pub mode: u32,
+    /// The offsets where the data is split into the segments or the chunks of the stream.
+    pub boundaries: Vec<usize>,
+    /// The size of the parts which each segment or chunk is split into by the wrapper, if any.
+    pub limit: Option<usize>,
+    /// The matches reported by the block mode scan only, the ID of the expression and the end offset.
+    pub missing: Matches,
+    /// The matches reported by this scan only.
+    pub unexpected: Matches,
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(
+            f,
+            "{} scan split at {:?}",
+            mode_name(self.mode),
+            self.boundaries
+        ));
+
+        if let Some(limit) = self.limit {
+            try!(write!(f, " in parts of {} bytes", limit));
+        }
+
+        try!(write!(f, ": missing {:?}", self.missing));
+        write!(f, ", unexpected {:?}", self.unexpected)
+    }
+}
+
+/// The result of `verify_consistency`.
+#[derive(Debug, Clone, Default)]
+pub struct ConsistencyReport {
+    /// The sorted matches of the block mode scan, which the other scans are compared with.
+    pub expected: Matches,
+    /// The number of the scans compared with the block mode scan.
+    pub scans: usize,
+    /// The scans whose matches differ from the block mode scan.
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+impl ConsistencyReport {
+    /// Whether all the scans reported the same matches.
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+impl fmt::Display for ConsistencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(
+            f,
+            "{} of {} scans inconsistent with {} block mode matches",
+            self.inconsistencies.len(),
+            self.scans,
+            self.expected.len()
+        ));
+
+        for inconsistency in &self.inconsistencies {
+            try!(write!(f, "\n  {}", inconsistency));
+        }
+
+        Ok(())
+    }
+}
+
+/// Verify that the patterns report the same matches on the data in the block, vectored and streaming modes.
+///
+/// Hyperscan guarantees the same matches in all the modes, so any difference comes from
+/// splitting the data or translating the offsets. The data is scanned as one block, and
+/// split into segments and stream chunks in many ways: as a whole, into single bytes,
+/// in two at each offset of the small data, at random offsets, and into the small parts
+/// which the wrapper scans the too large segments and chunks in. The `(id, to)` multiset
+/// of each scan is compared with the block mode scan.
+pub fn verify_consistency(patterns: &Patterns, data: &[u8]) -> Result<ConsistencyReport, Error> {
+    let block = try!(harness::compile(patterns, HS_MODE_BLOCK));
+    let mut expected = try!(harness::scan(&block, data, &[], None));
+
+    expected.sort();
+
+    let mut splits: Vec<(Vec<usize>, Option<usize>)> = vec![(vec![], None)];
+
+    if data.len() <= EXHAUSTIVE_LEN {
+        splits.push(((1..data.len()).collect(), None));
+        splits.extend((0..data.len() + 1).map(|off| (vec![off], None)));
+    }
+
+    let mut rng = Rng::new(data.len() as u64);
+
+    for _ in 0..RANDOM_SPLITS {
+        splits.push((harness::random_boundaries(&mut rng, data.len(), MAX_RANDOM_CUTS), None));
+    }
+
+    for &limit in &LIMITS {
+        splits.push((vec![], Some(limit)));
+        splits.push((
+            harness::random_boundaries(&mut rng, data.len(), MAX_RANDOM_CUTS),
+            Some(limit),
+        ));
+    }
+
+    let mut report = ConsistencyReport {
+        expected: expected,
+        scans: 0,
+        inconsistencies: Vec::new(),
+    };
+
+    for &mode in &[HS_MODE_VECTORED, HS_MODE_STREAM] {
+        let db = try!(harness::compile(patterns, mode));
+
+        for &(ref boundaries, limit) in &splits {
+            let mut actual = try!(harness::scan(&db, data, boundaries, limit));
+
+            actual.sort();
+
+            report.scans += 1;
+
+            if let Some(inconsistency) = compare(&report.expected, &actual, mode, boundaries, limit) {
+                debug!("inconsistent matches, {}", inconsistency);
+
+                report.inconsistencies.push(inconsistency);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// The inconsistency of the sorted `actual` matches of a scan with the sorted `expected` matches, if any.
+fn compare(
+    expected: &[(u32, u64)],
+    actual: &[(u32, u64)],
+    mode: u32,
+    boundaries: &[usize],
+    limit: Option<usize>,
+) -> Option<Inconsistency> {
+    let (missing, unexpected) = diff(expected, actual);
+
+    if missing.is_empty() && unexpected.is_empty() {
+        return None;
+    }
+
+    Some(Inconsistency {
+        mode: mode,
+        boundaries: boundaries.to_vec(),
+        limit: limit,
+        missing: missing,
+        unexpected: unexpected,
+    })
+}
+
+/// The matches in the sorted `expected` only, and in the sorted `actual` only, counting the duplicates.
+fn diff(expected: &[(u32, u64)], actual: &[(u32, u64)]) -> (Matches, Matches) {
+    let mut missing = Vec::new();
+    let mut unexpected = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < expected.len() || j < actual.len() {
+        if j == actual.len() || (i < expected.len() && expected[i] < actual[j]) {
+            missing.push(expected[i]);
+            i += 1;
+        } else if i == expected.len() || actual[j] < expected[i] {
+            unexpected.push(actual[j]);
+            j += 1;
+        } else {
+            i += 1;
+            j += 1;
+        }
+    }
+
+    (missing, unexpected)
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+    use super::{compare, diff};
+
+    #[test]
+    fn test_verify_consistency() {
+        let _ = env_logger::try_init();
+
+        let patterns = patterns!(["foo", "o+b", r"\bbar\b", "r$"]);
+        let report = verify_consistency(&patterns, b"foo bar foobar\nfoooobar").unwrap();
+
+        assert!(report.is_consistent(), "{}", report);
+        assert!(report.scans > 0);
+        assert_eq!(report.expected.iter().filter(|&&(id, _)| id == 1).count(), 3);
+    }
+
+    #[test]
+    fn test_verify_consistency_with_som() {
+        let _ = env_logger::try_init();
+
+        let patterns = patterns!(["foo", "o+b"], flags => HS_FLAG_SOM_LEFTMOST);
+        let report = verify_consistency(&patterns, b"foo bar foobar\nfoooobar").unwrap();
+
+        assert!(report.is_consistent(), "{}", report);
+        assert_eq!(report.expected.len(), 5);
+    }
+
+    #[test]
+    fn test_report_inconsistency() {
+        let expected = vec![(1, 3), (2, 7)];
+
+        assert_eq!(compare(&expected, &expected, HS_MODE_STREAM, &[2], Some(3)), None);
+
+        // a match lost at a boundary and reported again at the end of the next part
+        let inconsistency = compare(&expected, &[(1, 3), (2, 9)], HS_MODE_STREAM, &[2], Some(3)).unwrap();
+
+        assert_eq!(inconsistency.missing, vec![(2, 7)]);
+        assert_eq!(inconsistency.unexpected, vec![(2, 9)]);
+
+        let report = ConsistencyReport {
+            expected: expected,
+            scans: 2,
+            inconsistencies: vec![inconsistency],
+        };
+
+        assert!(!report.is_consistent());
+        assert_eq!(
+            report.to_string(),
+            "1 of 2 scans inconsistent with 2 block mode matches\n  \
+             streaming scan split at [2] in parts of 3 bytes: missing [(2, 7)], unexpected [(2, 9)]"
+        );
+    }
+
+    #[test]
+    fn test_diff_matches() {
+        assert_eq!(diff(&[(1, 3), (1, 3)], &[(1, 3), (1, 3)]), (vec![], vec![]));
+        assert_eq!(
+            diff(&[(1, 3), (1, 3), (2, 5)], &[(1, 3), (2, 6)]),
+            (vec![(1, 3), (2, 5)], vec![(2, 6)])
+        );
+    }
+}
@@ -2,7 +2,7 @@ use std::ptr;
 use std::fmt;
 use std::ops::{Deref, DerefMut};
 
-use libc;
+use alloc::free_misc;
 
 pub struct CPtr<T: Send>(*mut T);
 
@@ -33,14 +33,18 @@ impl<T: Send> CPtr<T> {
 impl<T: Send> Drop for CPtr<T> {
     #[inline]
     fn drop(&mut self) {
+        if self.0.is_null() {
+            return;
+        }
+
         unsafe {
             // Copy the object out from the pointer onto the stack,
             // where it is covered by normal Rust destructor semantics
             // and cleans itself up, if necessary
             ptr::read(self.0 as *const T);
 
-            // clean-up our allocation
-            libc::free(self.0 as *mut libc::c_void);
+            // clean-up our allocation with the allocator of Hyperscan
+            free_misc(self.0);
 
             self.0 = ptr::null_mut();
         }
@@ -75,9 +79,9 @@ pub mod tests {
     use std::ptr;
     use std::mem;
 
-    use libc;
     use regex::Regex;
 
+    use super::super::alloc::alloc_misc;
     use super::*;
 
     struct Foo {
@@ -87,7 +91,7 @@ pub mod tests {
     #[test]
     fn test_from_ptr() {
         unsafe {
-            let foo = libc::malloc(mem::size_of::<Foo>() as libc::size_t) as *mut Foo;
+            let foo = alloc_misc::<Foo>(mem::size_of::<Foo>());
 
             (*foo).bar = 32;
 
@@ -0,0 +1,518 @@
+use std::ascii;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::fmt;
+
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::ast::{self, Assertion, AssertionKind, Ast, ClassPerl, ClassPerlKind, ClassSetItem, Span};
+
+use any::AnyDatabase;
+use compile::{Pattern, Patterns};
+use constants::*;
+use errors::Error;
+use harness::{self, mode_name, Rng};
+
+/// The modes in which the patterns are checked.
+const MODES: [u32; 3] = [HS_MODE_BLOCK, HS_MODE_VECTORED, HS_MODE_STREAM];
+
+/// The flags which can't be translated to the `regex` crate.
+const UNSUPPORTED_FLAGS: u32 = HS_FLAG_COMBINATION | HS_FLAG_QUIET | HS_FLAG_PREFILTER;
+
+/// The end offsets of the matches of a pattern, computed with the `regex` crate.
+struct Oracle {
+    /// Matches the pattern followed by one byte at the end of the data,
+    /// so the assertions at the end of the match can see the next byte.
+    before_byte: Regex,
+    /// Matches the pattern at the end of the data.
+    at_end: Regex,
+    single_match: bool,
+}
+
+impl Oracle {
+    fn new(pattern: &Pattern) -> Result<Oracle, String> {
+        if pattern.flags.0 & UNSUPPORTED_FLAGS != 0 {
+            return Err(format!("unsupported flags `{}`", pattern.flags));
+        }
+
+        let utf8 = pattern.flags.is_set(HS_FLAG_UTF8);
+        let ucp = pattern.flags.is_set(HS_FLAG_UCP);
+
+        // without `HS_FLAG_UCP`, the classes like `\w` and `\b` match the ASCII characters only
+        let expression = if utf8 && !ucp {
+            try!(ascii_classes(&pattern.expression))
+        } else {
+            pattern.expression.clone()
+        };
+
+        let build = |suffix: &str| {
+            RegexBuilder::new(&format!("(?:{}){}", expression, suffix))
+                .case_insensitive(pattern.flags.is_set(HS_FLAG_CASELESS))
+                .multi_line(pattern.flags.is_set(HS_FLAG_MULTILINE))
+                .dot_matches_new_line(pattern.flags.is_set(HS_FLAG_DOTALL))
+                .unicode(utf8 || ucp)
+                .build()
+                .map_err(|err| err.to_string())
+        };
+
+        Ok(Oracle {
+            before_byte: try!(build(r"(?s-u:.)\z")),
+            at_end: try!(build(r"\z")),
+            single_match: pattern.flags.is_set(HS_FLAG_SINGLEMATCH),
+        })
+    }
+
+    /// The offsets where a match of the pattern ends, like Hyperscan reports all of them.
+    fn ends(&self, data: &[u8]) -> BTreeSet<u64> {
+        let mut ends = BTreeSet::new();
+
+        for end in 0..data.len() + 1 {
+            let matched = if end < data.len() {
+                self.before_byte.is_match(&data[..end + 1])
+            } else {
+                self.at_end.is_match(data)
+            };
+
+            if matched {
+                ends.insert(end as u64);
+
+                if self.single_match {
+                    break;
+                }
+            }
+        }
+
+        ends
+    }
+}
+
+/// Rewrite the Perl classes and the word boundaries of the expression to their ASCII forms,
+/// which the `regex` crate matches in the Unicode mode like Hyperscan in the UTF-8 mode without `HS_FLAG_UCP`.
+fn ascii_classes(expression: &str) -> Result<String, String> {
+    let ast = try!(ast::parse::Parser::new()
+        .parse(expression)
+        .map_err(|err| err.to_string()));
+    let replacements = ast::visit(&ast, AsciiClasses(Vec::new())).unwrap_or_else(|never| match never {});
+    let mut expression = expression.to_owned();
+
+    // the spans are visited in order, replace them from the end to keep the offsets valid
+    for (span, replacement) in replacements.into_iter().rev() {
+        expression.replace_range(span.start.offset..span.end.offset, &replacement);
+    }
+
+    Ok(expression)
+}
+
+/// Collects the replacements of the Perl classes and the word boundaries.
+struct AsciiClasses(Vec<(Span, String)>);
+
+impl ast::Visitor for AsciiClasses {
+    type Output = Vec<(Span, String)>;
+    type Err = Infallible;
+
+    fn finish(self) -> Result<Self::Output, Self::Err> {
+        Ok(self.0)
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), Self::Err> {
+        match *ast {
+            Ast::Class(ast::Class::Perl(ref class)) => {
+                self.0.push((class.span, format!("[{}]", ascii_class(class))));
+            }
+            Ast::Assertion(Assertion {
+                span,
+                kind: AssertionKind::WordBoundary,
+            }) => self.0.push((span, r"(?-u:\b)".to_owned())),
+            Ast::Assertion(Assertion {
+                span,
+                kind: AssertionKind::NotWordBoundary,
+            }) => self.0.push((span, r"(?-u:\B)".to_owned())),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn visit_class_set_item_pre(&mut self, item: &ClassSetItem) -> Result<(), Self::Err> {
+        if let ClassSetItem::Perl(ref class) = *item {
+            self.0.push((class.span, ascii_class(class)));
+        }
+
+        Ok(())
+    }
+}
+
+/// The ASCII class matching the characters of the Perl class, like `[:^word:]` for `\W`.
+fn ascii_class(class: &ClassPerl) -> String {
+    let name = match class.kind {
+        ClassPerlKind::Digit => "digit",
+        ClassPerlKind::Space => "space",
+        ClassPerlKind::Word => "word",
+    };
+
+    format!("[:{}{}:]", if class.negated { "^" } else { "" }, name)
+}
+
+/// A pattern whose matches reported by Hyperscan differ from the `regex` crate.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// The diverging pattern.
+    pub pattern: Pattern,
+    /// The mode of the scan, one of `HS_MODE_BLOCK`, `HS_MODE_STREAM` and `HS_MODE_VECTORED`.
+    pub mode: u32,
+    /// The minimized data which reproduces the divergence.
+    pub data: Vec<u8>,
+    /// The offsets where the data is split into the segments or the chunks of the stream.
+    pub boundaries: Vec<usize>,
+    /// The end offsets of the matches found by the `regex` crate.
+    pub expected: Vec<u64>,
+    /// The end offsets of the matches reported by Hyperscan.
+    pub actual: Vec<u64>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let data: Vec<u8> = self.data.iter().flat_map(|&b| ascii::escape_default(b)).collect();
+
+        try!(writeln!(
+            f,
+            "pattern `{}` diverges in {} mode",
+            self.pattern,
+            mode_name(self.mode)
+        ));
+        try!(writeln!(f, "  data: b\"{}\"", String::from_utf8_lossy(&data)));
+
+        if self.mode != HS_MODE_BLOCK {
+            try!(writeln!(f, "  split at: {:?}", self.boundaries));
+        }
+
+        try!(writeln!(f, "  regex: {:?}", self.expected));
+        write!(f, "  hyperscan: {:?}", self.actual)
+    }
+}
+
+/// The result of a differential check.
+#[derive(Debug, Clone, Default)]
+pub struct DifferentialReport {
+    /// The patterns diverging from the `regex` crate, one per pattern and mode.
+    pub divergences: Vec<Divergence>,
+    /// The patterns which can't be checked, such as using a syntax unsupported by the `regex` crate or Hyperscan.
+    pub skipped: Vec<(Pattern, String)>,
+}
+
+impl DifferentialReport {
+    /// Whether no pattern diverges.
+    pub fn is_ok(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// Check the matches reported by Hyperscan against the `regex` crate,
+/// to qualify the new pattern sets and versions of Hyperscan.
+///
+/// The end offsets of the matches are compared for each pattern, scanning the corpus
+/// in block mode, in vectored mode split into random segments, and in streaming mode
+/// split into random chunks. The divergences are minimized to small reproducers.
+///
+/// The patterns are translated to the `regex` syntax as is, so the PCRE features which
+/// behave differently, such as `$` matching before a trailing newline, are reported too.
+/// Each corpus is scanned by the `regex` crate once per byte, so it should be kept small.
+#[derive(Debug, Clone)]
+pub struct DifferentialChecker {
+    patterns: Patterns,
+    seed: u64,
+    iterations: usize,
+    max_cuts: usize,
+    minimize_attempts: usize,
+}
+
+impl DifferentialChecker {
+    /// Construct a checker of the patterns.
+    pub fn new(patterns: Patterns) -> DifferentialChecker {
+        DifferentialChecker {
+            patterns: patterns,
+            seed: 0,
+            iterations: 16,
+            max_cuts: 16,
+            minimize_attempts: 1000,
+        }
+    }
+
+    /// The seed of the random segments and chunks, to reproduce a check.
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+
+    /// The number of the random ways to split each corpus in the vectored and streaming modes.
+    pub fn iterations(&mut self, iterations: usize) -> &mut Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// The maximum number of the offsets to split each corpus at.
+    pub fn max_cuts(&mut self, max_cuts: usize) -> &mut Self {
+        self.max_cuts = max_cuts;
+        self
+    }
+
+    /// The maximum number of the candidates tried to minimize each divergence.
+    pub fn minimize_attempts(&mut self, attempts: usize) -> &mut Self {
+        self.minimize_attempts = attempts;
+        self
+    }
+
+    /// Check the patterns against each corpus.
+    pub fn check<I, T>(&self, corpus: I) -> Result<DifferentialReport, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let mut report = DifferentialReport::default();
+        let mut checked = Vec::new();
+
+        for pattern in &self.patterns {
+            match Oracle::new(pattern).and_then(|oracle| compile_alone(pattern).map(|_| oracle)) {
+                Ok(oracle) => checked.push((pattern, oracle)),
+                Err(reason) => {
+                    debug!("skip pattern `{}`, {}", pattern, reason);
+
+                    report.skipped.push((pattern.clone(), reason));
+                }
+            }
+        }
+
+        if checked.is_empty() {
+            return Ok(report);
+        }
+
+        // the patterns may share an ID, identify them by their index in the databases
+        let patterns: Patterns = checked
+            .iter()
+            .enumerate()
+            .map(|(i, &(pattern, _))| Pattern {
+                id: i,
+                ..pattern.clone()
+            })
+            .collect();
+        let mut databases = Vec::with_capacity(MODES.len());
+
+        for &mode in &MODES {
+            databases.push((mode, try!(harness::compile(&patterns, mode))));
+        }
+
+        let mut rng = Rng::new(self.seed);
+        let mut diverged = BTreeSet::new();
+
+        for data in corpus {
+            let data = data.as_ref();
+            let expected: Vec<BTreeSet<u64>> = checked.iter().map(|&(_, ref oracle)| oracle.ends(data)).collect();
+
+            for &(mode, ref db) in &databases {
+                let rounds = if mode == HS_MODE_BLOCK {
+                    1
+                } else {
+                    self.iterations.max(1)
+                };
+
+                for round in 0..rounds {
+                    let boundaries = if mode == HS_MODE_BLOCK || round == 0 {
+                        vec![]
+                    } else {
+                        harness::random_boundaries(&mut rng, data.len(), self.max_cuts)
+                    };
+
+                    let actual = by_pattern(try!(harness::scan(db, data, &boundaries, None)));
+
+                    for (i, &(pattern, ref oracle)) in checked.iter().enumerate() {
+                        let ends = ends_of(&actual, i, oracle);
+
+                        if diverged.contains(&(mode, i)) || ends == expected[i] {
+                            continue;
+                        }
+
+                        let divergence = try!(self.minimize(pattern, oracle, mode, data, &boundaries, ends));
+
+                        debug!("{}", divergence);
+
+                        diverged.insert((mode, i));
+                        report.divergences.push(divergence);
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Shrink the data which reproduces the divergence of the pattern, scanned alone,
+    /// `shared` are the end offsets reported by the database shared with the other patterns.
+    fn minimize(
+        &self,
+        pattern: &Pattern,
+        oracle: &Oracle,
+        mode: u32,
+        data: &[u8],
+        boundaries: &[usize],
+        shared: BTreeSet<u64>,
+    ) -> Result<Divergence, Error> {
+        let db = try!(harness::compile(&vec![pattern.clone()], mode));
+        let diverges = |db: &AnyDatabase, data: &[u8], boundaries: &[usize]| -> Result<bool, Error> {
+            let actual = by_pattern(try!(harness::scan(db, data, boundaries, None)));
+
+            Ok(ends_of(&actual, pattern.id, oracle) != oracle.ends(data))
+        };
+
+        // the divergence may come from the other patterns sharing the database, report it as is
+        if !try!(diverges(&db, data, boundaries)) {
+            return Ok(Divergence {
+                pattern: pattern.clone(),
+                mode: mode,
+                data: data.to_vec(),
+                boundaries: boundaries.to_vec(),
+                expected: oracle.ends(data).into_iter().collect(),
+                actual: shared.into_iter().collect(),
+            });
+        }
+
+        let (data, boundaries) = harness::minimize(data, boundaries, self.minimize_attempts, |data, boundaries| {
+            diverges(&db, data, boundaries).unwrap_or(false)
+        });
+
+        let actual = by_pattern(try!(harness::scan(&db, &data, &boundaries, None)));
+
+        Ok(Divergence {
+            pattern: pattern.clone(),
+            mode: mode,
+            expected: oracle.ends(&data).into_iter().collect(),
+            actual: ends_of(&actual, pattern.id, oracle).into_iter().collect(),
+            data: data,
+            boundaries: boundaries,
+        })
+    }
+}
+
+/// Compile the pattern alone in each mode, so that a pattern rejected by Hyperscan is skipped
+/// instead of failing the check of the whole set.
+fn compile_alone(pattern: &Pattern) -> Result<(), String> {
+    for &mode in &MODES {
+        try!(harness::compile(&vec![pattern.clone()], mode).map_err(|err| err.to_string()));
+    }
+
+    Ok(())
+}
+
+fn by_pattern(matches: harness::Matches) -> BTreeMap<u32, BTreeSet<u64>> {
+    let mut ends = BTreeMap::new();
+
+    for (id, to) in matches {
+        ends.entry(id).or_insert_with(BTreeSet::new).insert(to);
+    }
+
+    ends
+}
+
+fn ends_of(actual: &BTreeMap<u32, BTreeSet<u64>>, id: usize, oracle: &Oracle) -> BTreeSet<u64> {
+    let ends = actual.get(&(id as u32)).cloned().unwrap_or_default();
+
+    if oracle.single_match {
+        ends.into_iter().take(1).collect()
+    } else {
+        ends
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+    use super::{ascii_classes, Oracle};
+
+    #[test]
+    fn test_differential_checker() {
+        let _ = env_logger::try_init();
+
+        let patterns = vec![
+            pattern! {"foo", flags => 0, id => 1},
+            pattern! {r"\bbar\b", flags => HS_FLAG_CASELESS, id => 2},
+            pattern! {"a+b", flags => 0, id => 3},
+            pattern! {r"(?=x)", flags => 0, id => 4},
+        ];
+        let corpus = ["foo bar foobar BAR aaab", "", "xfoo\nbar\n"];
+
+        let report = DifferentialChecker::new(patterns).seed(1).check(&corpus).unwrap();
+
+        assert!(report.is_ok(), "{:?}", report.divergences);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0.id, 4);
+    }
+
+    #[test]
+    fn test_ascii_classes() {
+        assert_eq!(
+            ascii_classes(r"\w+\b[\D\s]\B").unwrap(),
+            r"[[:word:]]+(?-u:\b)[[:^digit:][:space:]](?-u:\B)"
+        );
+        assert_eq!(ascii_classes(r"a\\w").unwrap(), r"a\\w");
+
+        // `é` is not a word character without `HS_FLAG_UCP`
+        let data = "é a".as_bytes();
+        let ascii = Oracle::new(&pattern! {r"\w+\b", flags => HS_FLAG_UTF8, id => 1}).unwrap();
+        let unicode = Oracle::new(&pattern! {r"\w+\b", flags => HS_FLAG_UTF8 | HS_FLAG_UCP, id => 1}).unwrap();
+
+        assert_eq!(ascii.ends(data).into_iter().collect::<Vec<_>>(), vec![4]);
+        assert_eq!(unicode.ends(data).into_iter().collect::<Vec<_>>(), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_differential_shared_id() {
+        let _ = env_logger::try_init();
+
+        // both patterns have the default ID 0
+        let patterns = vec![pattern! {"foo"}, pattern! {"bar"}];
+
+        let report = DifferentialChecker::new(patterns)
+            .seed(1)
+            .check(&["foo bar", "barfoo"])
+            .unwrap();
+
+        assert!(report.is_ok(), "{:?}", report.divergences);
+        assert!(report.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_differential_skip_invalid() {
+        let _ = env_logger::try_init();
+
+        // Hyperscan rejects the patterns matching the empty data without `HS_FLAG_ALLOWEMPTY`
+        let patterns = vec![
+            pattern! {"foo", flags => 0, id => 1},
+            pattern! {"a*", flags => 0, id => 2},
+        ];
+
+        let report = DifferentialChecker::new(patterns).check(&["foo"]).unwrap();
+
+        assert!(report.is_ok(), "{:?}", report.divergences);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0.id, 2);
+    }
+
+    #[test]
+    fn test_differential_divergence() {
+        let _ = env_logger::try_init();
+
+        // Hyperscan matches `$` before a trailing newline, unlike the `regex` crate
+        let report = DifferentialChecker::new(vec![pattern! {"foo$", flags => 0, id => 1}])
+            .check(&["xx foo\n"])
+            .unwrap();
+
+        assert_eq!(report.divergences.len(), 3);
+
+        let divergence = &report.divergences[0];
+
+        assert_eq!(divergence.mode, HS_MODE_BLOCK);
+        assert_eq!(divergence.data, b"foo\n");
+        assert_eq!(divergence.expected, Vec::<u64>::new());
+        assert_eq!(divergence.actual, vec![3]);
+    }
+}
@@ -0,0 +1,166 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use api::*;
+use bundle::DatabaseBundle;
+use compile::{Pattern, Patterns};
+use errors::{Error, ScanError};
+
+/// Parse the patterns, one `id:/expression/flags` per line,
+/// ignoring the empty lines and the comments starting with `#`.
+pub fn parse_patterns(s: &str) -> Result<Patterns, Error> {
+    s.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Pattern::parse)
+        .collect()
+}
+
+/// Compile the patterns file into a bundle of the modes for the target platform,
+/// and write it to `$OUT_DIR/{name}.hsbundle`, to be embedded with `include_database!`.
+///
+/// It is intended to be called from a build script.
+pub fn embed_database<P: AsRef<Path>>(
+    patterns_file: P,
+    name: &str,
+    modes: u32,
+    platform: &PlatformInfo,
+) -> Result<PathBuf, ScanError> {
+    let out_dir = try!(env::var_os("OUT_DIR").ok_or_else(|| io::Error::new(
+        io::ErrorKind::NotFound,
+        "OUT_DIR is not set, the database should be embedded from a build script",
+    )));
+    let path = Path::new(&out_dir).join(format!("{}.hsbundle", name));
+
+    try!(embed_database_to(patterns_file.as_ref(), &path, modes, platform));
+
+    println!("cargo:rerun-if-changed={}", patterns_file.as_ref().display());
+
+    Ok(path)
+}
+
+/// Compile the patterns file into a bundle of the modes for the target platform, and write it to the path.
+pub fn embed_database_to<P: AsRef<Path>, Q: AsRef<Path>>(
+    patterns_file: P,
+    path: Q,
+    modes: u32,
+    platform: &PlatformInfo,
+) -> Result<(), ScanError> {
+    let patterns = try!(parse_patterns(&try!(fs::read_to_string(patterns_file.as_ref()))));
+
+    debug!(
+        "embed {} patterns from `{}` to `{}`",
+        patterns.len(),
+        patterns_file.as_ref().display(),
+        path.as_ref().display()
+    );
+
+    let bundle = try!(DatabaseBundle::build_for_platform(patterns, modes, platform));
+
+    try!(bundle.save(path));
+
+    Ok(())
+}
+
+/// Embed a bundle written by `embed_database` in the build script, and lazily deserialize
+/// the database of the mode into a `static` on the first use, returning a `&'static` reference.
+///
+/// If the embedded database can't be loaded on the host, such as being built for another platform,
+/// it's compiled from the embedded source patterns instead.
+///
+/// ```rust,ignore
+/// fn rules() -> &'static BlockDatabase {
+///     include_database!(BlockDatabase, concat!(env!("OUT_DIR"), "/rules.hsbundle"))
+/// }
+/// ```
+#[macro_export]
+macro_rules! include_database {
+    (BlockDatabase, $path:expr) => {
+        include_database!(@load BlockDatabase, HS_MODE_BLOCK, take_block, $path)
+    };
+    (StreamingDatabase, $path:expr) => {
+        include_database!(@load StreamingDatabase, HS_MODE_STREAM, take_streaming, $path)
+    };
+    (VectoredDatabase, $path:expr) => {
+        include_database!(@load VectoredDatabase, HS_MODE_VECTORED, take_vectored, $path)
+    };
+    (@load $db:ident, $mode:ident, $take:ident, $path:expr) => {{
+        static DATABASE: ::std::sync::OnceLock<$crate::$db> = ::std::sync::OnceLock::new();
+
+        DATABASE.get_or_init(|| {
+            $crate::DatabaseBundle::deserialize_or_compile(include_bytes!($path), $crate::$mode)
+                .expect("load embedded database bundle")
+                .$take()
+                .expect(concat!("embedded database bundle without ", stringify!($db)))
+        })
+    }};
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::ptr;
+
+    use super::super::*;
+    use super::super::harness::on_match;
+    use super::*;
+
+    #[test]
+    fn test_parse_patterns() {
+        let patterns = parse_patterns("# comment\n\n1:/foo/i\n 2:/bar$/m \n").unwrap();
+
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(patterns[0].expression, "foo");
+        assert_eq!(patterns[0].flags, CompileFlags(HS_FLAG_CASELESS));
+        assert_eq!(patterns[1].id, 2);
+
+        assert!(parse_patterns("x:/foo/").is_err());
+    }
+
+    #[test]
+    fn test_embed_database() {
+        let _ = env_logger::try_init();
+
+        let dir = env::temp_dir();
+        let patterns_file = dir.join(format!("hyperscan-{}-embed.rules", process::id()));
+        let bundle_file = dir.join(format!("hyperscan-{}-embed.hsbundle", process::id()));
+
+        fs::write(&patterns_file, "1:/test/\n2:/foo/i\n").unwrap();
+
+        embed_database_to(&patterns_file, &bundle_file, HS_MODE_BLOCK, &PlatformInfo::host()).unwrap();
+
+        let bundle = DatabaseBundle::load(&bundle_file).unwrap();
+
+        assert_eq!(bundle.modes(), HS_MODE_BLOCK);
+        assert_eq!(bundle.pattern(2).unwrap().expression, "foo");
+
+        fs::remove_file(&patterns_file).unwrap();
+        fs::remove_file(&bundle_file).unwrap();
+    }
+
+    fn embedded() -> &'static BlockDatabase {
+        // the fixture bundles a block database which can't be loaded, so it's compiled from the patterns
+        include_database!(BlockDatabase, "../tests/fixtures/embed.hsbundle")
+    }
+
+    #[test]
+    fn test_include_database() {
+        let _ = env_logger::try_init();
+
+        let db = embedded();
+        let s = db.alloc().unwrap();
+        let matches = RefCell::new(Vec::new());
+
+        db.scan("FOO test", 0, &s, Some(on_match), Some(&matches)).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(2, 3), (1, 8)]);
+        assert!(ptr::eq(embedded(), db));
+    }
+}
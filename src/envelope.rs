@@ -0,0 +1,294 @@
+use std::fmt;
+use std::str;
+
+#[cfg(feature = "ed25519-dalek")]
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use api::*;
+use codec::{put_bytes, put_u32, Reader};
+use common::RawDatabase;
+use errors::Error;
+
+/// The magic number at the beginning of the envelopes.
+const ENVELOPE_MAGIC: &[u8; 8] = b"HSDBENV1";
+
+/// The version of the envelope format.
+const ENVELOPE_VERSION: u32 = 1;
+
+/// The envelope is signed with an Ed25519 key.
+const FLAG_SIGNED: u32 = 1;
+
+const CHECKSUM_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+
+/// An integrity-checked envelope around a serialized database.
+///
+/// The envelope starts with `HSDBENV1`, the format version and flags, followed by the metadata
+/// (the mode and the information of the database), the payload (the serialized database),
+/// the SHA-256 checksum of all the preceding bytes, and an optional Ed25519 signature
+/// of all the preceding bytes including the checksum. All the integers are little-endian.
+///
+/// The envelope is verified without passing any bytes to Hyperscan.
+#[derive(Clone, Copy)]
+pub struct DatabaseEnvelope<'a> {
+    mode: u32,
+    info: &'a str,
+    payload: &'a [u8],
+    signed: bool,
+}
+
+impl<'a> fmt::Debug for DatabaseEnvelope<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "DatabaseEnvelope{{mode: {}, info: {:?}, len: {}, signed: {}}}",
+            self.mode,
+            self.info,
+            self.payload.len(),
+            self.signed
+        )
+    }
+}
+
+impl<'a> DatabaseEnvelope<'a> {
+    /// Whether the bytes start like an envelope, without verifying it.
+    pub fn is_envelope(bytes: &[u8]) -> bool {
+        bytes.starts_with(ENVELOPE_MAGIC)
+    }
+
+    /// Wrap the serialized database in an envelope with a checksum.
+    pub fn seal<S: SerializedDatabase + ?Sized>(data: &S) -> Result<Vec<u8>, Error> {
+        let mut buf = try!(seal_payload(data, 0));
+
+        put_checksum(&mut buf);
+
+        Ok(buf)
+    }
+
+    /// Wrap the serialized database in an envelope with a checksum, and sign it with the key.
+    #[cfg(feature = "ed25519-dalek")]
+    pub fn seal_signed<S: SerializedDatabase + ?Sized>(data: &S, key: &SigningKey) -> Result<Vec<u8>, Error> {
+        let mut buf = try!(seal_payload(data, FLAG_SIGNED));
+
+        put_checksum(&mut buf);
+
+        let signature = key.sign(&buf);
+
+        buf.extend_from_slice(&signature.to_bytes());
+
+        Ok(buf)
+    }
+
+    /// Verify the checksum of the envelope, and parse its metadata.
+    ///
+    /// The signature of a signed envelope is not verified, use `open_signed` to require it.
+    pub fn open(bytes: &'a [u8]) -> Result<DatabaseEnvelope<'a>, Error> {
+        let (envelope, signed_len) = try!(DatabaseEnvelope::parse(bytes));
+
+        let len = if envelope.signed {
+            signed_len + SIGNATURE_LEN
+        } else {
+            signed_len
+        };
+
+        if bytes.len() != len {
+            return Err(Error::Invalid);
+        }
+
+        Ok(envelope)
+    }
+
+    /// Verify the checksum and the signature of the envelope with the key, and parse its metadata.
+    ///
+    /// The unsigned envelopes are rejected with `Error::BadSignature`.
+    #[cfg(feature = "ed25519-dalek")]
+    pub fn open_signed(bytes: &'a [u8], key: &VerifyingKey) -> Result<DatabaseEnvelope<'a>, Error> {
+        let (envelope, signed_len) = try!(DatabaseEnvelope::parse(bytes));
+
+        if !envelope.signed || bytes.len() != signed_len + SIGNATURE_LEN {
+            return Err(Error::BadSignature);
+        }
+
+        let mut signature = [0; SIGNATURE_LEN];
+
+        signature.copy_from_slice(&bytes[signed_len..]);
+
+        try!(key
+            .verify_strict(&bytes[..signed_len], &Signature::from_bytes(&signature))
+            .map_err(|_| Error::BadSignature));
+
+        Ok(envelope)
+    }
+
+    /// Parse the envelope and verify its checksum,
+    /// returns the length of the signed bytes, which may be followed by the signature.
+    fn parse(bytes: &'a [u8]) -> Result<(DatabaseEnvelope<'a>, usize), Error> {
+        let mut r = Reader(bytes);
+
+        if try!(r.take(ENVELOPE_MAGIC.len())) != ENVELOPE_MAGIC || try!(r.u32()) != ENVELOPE_VERSION {
+            return Err(Error::Invalid);
+        }
+
+        let flags = try!(r.u32());
+        let mode = try!(r.u32());
+        let info = try!(r.bytes());
+        let payload = try!(r.bytes());
+
+        let checked_len = bytes.len() - r.remaining().len();
+        let checksum = try!(r.take(CHECKSUM_LEN));
+
+        if Sha256::digest(&bytes[..checked_len]).as_slice() != checksum {
+            debug!("checksum of database envelope mismatched");
+
+            return Err(Error::BadChecksum);
+        }
+
+        let envelope = DatabaseEnvelope {
+            mode: mode,
+            info: try!(str::from_utf8(info).map_err(|_| Error::Invalid)),
+            payload: payload,
+            signed: flags & FLAG_SIGNED != 0,
+        };
+
+        Ok((envelope, checked_len + CHECKSUM_LEN))
+    }
+
+    /// The mode of the serialized database.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// The information of the serialized database, such as `Version: 5.0.0 Features: AVX2 Mode: BLOCK`.
+    pub fn info(&self) -> &'a str {
+        self.info
+    }
+
+    /// The serialized database.
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    /// Whether the envelope is signed.
+    pub fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    /// Reconstruct the pattern database from the verified payload.
+    pub fn deserialize<T: Type>(&self) -> Result<RawDatabase<T>, Error> {
+        if self.mode != T::mode() {
+            return Err(Error::DbModeError);
+        }
+
+        RawDatabase::deserialize(self.payload)
+    }
+}
+
+fn seal_payload<S: SerializedDatabase + ?Sized>(data: &S, flags: u32) -> Result<Vec<u8>, Error> {
+    let info = try!(data.database_info());
+    let mode = try!(info.parse::<DatabaseInfo>()).mode;
+    let payload = data.as_slice();
+
+    let mut buf = Vec::with_capacity(payload.len() + info.len() + 128);
+
+    buf.extend_from_slice(ENVELOPE_MAGIC);
+    put_u32(&mut buf, ENVELOPE_VERSION);
+    put_u32(&mut buf, flags);
+    put_u32(&mut buf, mode);
+    put_bytes(&mut buf, info.as_bytes());
+    put_bytes(&mut buf, payload);
+
+    Ok(buf)
+}
+
+fn put_checksum(buf: &mut Vec<u8>) {
+    let checksum = Sha256::digest(&buf[..]);
+
+    buf.extend_from_slice(checksum.as_slice());
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_database_envelope() {
+        let _ = env_logger::try_init();
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let data = db.serialize().unwrap();
+
+        let sealed = DatabaseEnvelope::seal(&data).unwrap();
+        let envelope = DatabaseEnvelope::open(&sealed).unwrap();
+
+        assert!(DatabaseEnvelope::is_envelope(&sealed));
+        assert!(!DatabaseEnvelope::is_envelope(data.as_slice()));
+        assert_eq!(envelope.mode(), HS_MODE_BLOCK);
+        assert_eq!(envelope.payload(), data.as_slice());
+        assert!(!envelope.is_signed());
+        assert!(envelope.deserialize::<Block>().is_ok());
+        assert_eq!(envelope.deserialize::<Streaming>().err(), Some(Error::DbModeError));
+
+        // flip a bit of the payload
+        let mut tampered = sealed.clone();
+        let off = tampered.len() / 2;
+
+        tampered[off] ^= 1;
+
+        assert_eq!(DatabaseEnvelope::open(&tampered).err(), Some(Error::BadChecksum));
+        assert_eq!(
+            DatabaseEnvelope::open(&sealed[..sealed.len() - 1]).err(),
+            Some(Error::Invalid)
+        );
+        assert!(DatabaseEnvelope::open(data.as_slice()).is_err());
+    }
+
+    #[cfg(feature = "ed25519-dalek")]
+    #[test]
+    fn test_signed_database_envelope() {
+        use ed25519_dalek::SigningKey;
+        use sha2::{Digest, Sha256};
+
+        let _ = env_logger::try_init();
+
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let other = SigningKey::from_bytes(&[2; 32]);
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let data = db.serialize().unwrap();
+
+        let sealed = DatabaseEnvelope::seal_signed(&data, &key).unwrap();
+        let envelope = DatabaseEnvelope::open_signed(&sealed, &key.verifying_key()).unwrap();
+
+        assert!(envelope.is_signed());
+        assert!(envelope.deserialize::<Streaming>().is_ok());
+
+        assert_eq!(
+            DatabaseEnvelope::open_signed(&sealed, &other.verifying_key()).err(),
+            Some(Error::BadSignature)
+        );
+        assert_eq!(
+            DatabaseEnvelope::open_signed(&DatabaseEnvelope::seal(&data).unwrap(), &key.verifying_key()).err(),
+            Some(Error::BadSignature)
+        );
+
+        // the checksum can be recomputed by an attacker, but not the signature
+        let mut tampered = sealed[..sealed.len() - 64 - 32].to_vec();
+        let off = tampered.len() - 1;
+
+        tampered[off] ^= 1;
+
+        let checksum = Sha256::digest(&tampered[..]);
+
+        tampered.extend_from_slice(checksum.as_slice());
+        tampered.extend_from_slice(&sealed[sealed.len() - 64..]);
+
+        assert!(DatabaseEnvelope::open(&tampered).is_ok());
+        assert_eq!(
+            DatabaseEnvelope::open_signed(&tampered, &key.verifying_key()).err(),
+            Some(Error::BadSignature)
+        );
+    }
+}
@@ -37,6 +37,14 @@ pub enum Error {
     BadAlloc,
     /// Unknown error code
     Failed(i32),
+    /// The data of the given length is too large to be scanned in one block.
+    TooLarge(usize),
+    /// The Hyperscan library linked at runtime is incompatible with the bindings.
+    Incompatible(String),
+    /// The checksum of the serialized data mismatched, it was corrupted or tampered.
+    BadChecksum,
+    /// The signature of the serialized data is missing or invalid.
+    BadSignature,
     /// An error which can be returned when parsing an integer.
     ParseError(::std::num::ParseIntError),
     /// An error returned from CString::new to indicate
@@ -80,6 +88,8 @@ impl fmt::Display for Error {
         match *self {
             Error::CompilerError(ref reason) => try!(write!(f, " {}", reason)),
             Error::Failed(ref code) => try!(write!(f, " Code: {}", code)),
+            Error::TooLarge(ref len) => try!(write!(f, " Length: {}", len)),
+            Error::Incompatible(ref reason) => try!(write!(f, " {}", reason)),
             _ => {}
         }
 
@@ -100,12 +110,108 @@ impl error::Error for Error {
             Error::BadAlign => "A parameter passed to this function was not correctly aligned.",
             Error::BadAlloc => "The memory allocator did not correctly return memory suitably aligned.",
             Error::Failed(..) => "Internal operation failed.",
+            Error::TooLarge(..) => "The data is too large to be scanned in one block.",
+            Error::Incompatible(..) => "The Hyperscan library is incompatible.",
+            Error::BadChecksum => "The checksum of the serialized data mismatched.",
+            Error::BadSignature => "The signature of the serialized data is missing or invalid.",
             Error::ParseError(ref err) => err.description(),
             Error::NulError(ref err) => err.description(),
         }
     }
 }
 
+/// Errors of scanning the data from an I/O source.
+#[derive(Debug)]
+pub enum ScanError {
+    /// An error of reading or writing the data.
+    Io(::std::io::Error),
+    /// An error of the Hyperscan engine.
+    Scan(Error),
+}
+
+impl From<::std::io::Error> for ScanError {
+    fn from(err: ::std::io::Error) -> ScanError {
+        ScanError::Io(err)
+    }
+}
+
+impl From<Error> for ScanError {
+    fn from(err: Error) -> ScanError {
+        ScanError::Scan(err)
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScanError::Io(ref err) => write!(f, "I/O error: {}", err),
+            ScanError::Scan(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for ScanError {
+    fn description(&self) -> &str {
+        match *self {
+            ScanError::Io(ref err) => err.description(),
+            ScanError::Scan(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ScanError::Io(ref err) => Some(err),
+            ScanError::Scan(ref err) => Some(err),
+        }
+    }
+}
+
+/// Errors of sharing a database through the shared memory.
+#[derive(Debug)]
+pub enum SharedError {
+    /// An error of creating, mapping or unlinking the shared memory.
+    Io(::std::io::Error),
+    /// An error of the Hyperscan engine.
+    Database(Error),
+}
+
+impl From<::std::io::Error> for SharedError {
+    fn from(err: ::std::io::Error) -> SharedError {
+        SharedError::Io(err)
+    }
+}
+
+impl From<Error> for SharedError {
+    fn from(err: Error) -> SharedError {
+        SharedError::Database(err)
+    }
+}
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SharedError::Io(ref err) => write!(f, "shared memory error: {}", err),
+            SharedError::Database(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for SharedError {
+    fn description(&self) -> &str {
+        match *self {
+            SharedError::Io(ref err) => err.description(),
+            SharedError::Database(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            SharedError::Io(ref err) => Some(err),
+            SharedError::Database(ref err) => Some(err),
+        }
+    }
+}
+
 macro_rules! check_hs_error {
     ($expr:expr) => {
         if $expr != $crate::HS_SUCCESS {
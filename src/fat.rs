@@ -0,0 +1,284 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use api::*;
+use codec::{put_bytes, put_u32, put_u64, Reader};
+use common::RawDatabase;
+use compile::Patterns;
+use errors::{Error, ScanError};
+
+/// The magic number at the beginning of the fat databases.
+const FAT_MAGIC: &[u8; 8] = b"HSFATDB1";
+
+/// The version of the fat database format.
+const FAT_VERSION: u32 = 1;
+
+/// A database serialized for one target platform.
+#[derive(Clone)]
+struct Variant {
+    tune: u32,
+    cpu_features: u64,
+    data: Vec<u8>,
+}
+
+impl Variant {
+    /// Whether the variant can be loaded on the platform, using none of the CPU features it lacks.
+    fn is_supported_by(&self, platform: &PlatformInfo) -> bool {
+        self.cpu_features & !platform.cpu_features() == 0
+    }
+}
+
+/// A pattern set compiled for several target platforms and stored in one artifact,
+/// so that it can be shipped to the hosts of different CPU generations.
+///
+/// The artifact starts with `HSFATDB1`, the format version and the mode, followed by
+/// the tune family, CPU features and serialized database of each variant.
+/// All the integers are little-endian.
+///
+/// The variant using the most CPU features supported by the host is loaded,
+/// preferring the one tuned for the host CPU family.
+#[derive(Clone)]
+pub struct FatDatabase {
+    mode: u32,
+    variants: Vec<Variant>,
+}
+
+impl fmt::Debug for FatDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "FatDatabase{{mode: {}, targets: [", self.mode));
+
+        for (i, variant) in self.variants.iter().enumerate() {
+            if i > 0 {
+                try!(write!(f, ", "));
+            }
+
+            try!(write!(f, "{}:{:#x}", variant.tune, variant.cpu_features));
+        }
+
+        write!(f, "]}}")
+    }
+}
+
+impl FatDatabase {
+    /// Compile the patterns into the databases of the mode for each platform.
+    pub fn build<T: Type>(patterns: &Patterns, platforms: &[PlatformInfo]) -> Result<FatDatabase, Error> {
+        if platforms.is_empty() {
+            return Err(Error::Invalid);
+        }
+
+        let mut variants = Vec::with_capacity(platforms.len());
+
+        for platform in platforms {
+            let db: RawDatabase<T> = try!(patterns.build_for_platform(platform));
+
+            variants.push(Variant {
+                tune: platform.tune(),
+                cpu_features: platform.cpu_features(),
+                data: try!(db.serialize()).as_slice().to_vec(),
+            });
+        }
+
+        debug!(
+            "compiled {} patterns to {} database for {} platforms",
+            patterns.len(),
+            T::name(),
+            variants.len()
+        );
+
+        Ok(FatDatabase {
+            mode: T::mode(),
+            variants: variants,
+        })
+    }
+
+    /// The mode of the databases.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// The target platforms of the databases.
+    pub fn targets(&self) -> Vec<PlatformInfo> {
+        self.variants
+            .iter()
+            .map(|variant| PlatformInfo::new(variant.tune, variant.cpu_features))
+            .collect()
+    }
+
+    /// The variants which can be loaded on the platform, the best match first.
+    fn candidates(&self, platform: &PlatformInfo) -> Vec<&Variant> {
+        let tune = platform.tune();
+        let mut candidates: Vec<_> = self
+            .variants
+            .iter()
+            .filter(|variant| variant.is_supported_by(platform))
+            .collect();
+
+        candidates.sort_by_key(|variant| {
+            (
+                variant.cpu_features.count_ones(),
+                variant.cpu_features,
+                variant.tune == tune,
+            )
+        });
+        candidates.reverse();
+
+        candidates
+    }
+
+    /// The serialized database which best matches the platform.
+    pub fn select(&self, platform: &PlatformInfo) -> Option<&[u8]> {
+        self.candidates(platform).first().map(|variant| variant.data.as_slice())
+    }
+
+    /// Deserialize the database which best matches the running host.
+    ///
+    /// The variants the host rejects are skipped, returns `Error::DbPlatformError` if none can be loaded.
+    pub fn load<T: Type>(&self) -> Result<RawDatabase<T>, Error> {
+        if self.mode != T::mode() {
+            return Err(Error::DbModeError);
+        }
+
+        let host = PlatformInfo::host();
+
+        for variant in self.candidates(&host) {
+            match RawDatabase::deserialize(&variant.data) {
+                Err(Error::DbPlatformError) => {
+                    debug!(
+                        "skip {} database for platform {}:{:#x}, rejected by the host",
+                        T::name(),
+                        variant.tune,
+                        variant.cpu_features
+                    );
+                }
+                res => {
+                    trace!(
+                        "load {} database for platform {}:{:#x} on host {}:{:#x}",
+                        T::name(),
+                        variant.tune,
+                        variant.cpu_features,
+                        host.tune(),
+                        host.cpu_features()
+                    );
+
+                    return res;
+                }
+            }
+        }
+
+        Err(Error::DbPlatformError)
+    }
+
+    /// Serialize the databases of all the platforms into one stream of bytes.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+
+        buf.extend_from_slice(FAT_MAGIC);
+        put_u32(&mut buf, FAT_VERSION);
+        put_u32(&mut buf, self.mode);
+        put_u32(&mut buf, self.variants.len() as u32);
+
+        for variant in &self.variants {
+            put_u32(&mut buf, variant.tune);
+            put_u64(&mut buf, variant.cpu_features);
+            put_bytes(&mut buf, &variant.data);
+        }
+
+        buf
+    }
+
+    /// Reconstruct the fat database from a stream of bytes previously generated by `FatDatabase::serialize()`.
+    ///
+    /// The databases are not passed to Hyperscan until one of them is loaded.
+    pub fn deserialize(bytes: &[u8]) -> Result<FatDatabase, Error> {
+        let mut r = Reader(bytes);
+
+        if try!(r.take(FAT_MAGIC.len())) != FAT_MAGIC || try!(r.u32()) != FAT_VERSION {
+            return Err(Error::Invalid);
+        }
+
+        let mode = try!(r.u32());
+        let count = try!(r.u32()) as usize;
+        let mut variants = Vec::with_capacity(count.min(r.remaining().len()));
+
+        for _ in 0..count {
+            variants.push(Variant {
+                tune: try!(r.u32()),
+                cpu_features: try!(r.u64()),
+                data: try!(r.bytes()).to_vec(),
+            });
+        }
+
+        if variants.is_empty() || !r.remaining().is_empty() {
+            return Err(Error::Invalid);
+        }
+
+        Ok(FatDatabase {
+            mode: mode,
+            variants: variants,
+        })
+    }
+
+    /// Write the serialized fat database to the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ScanError> {
+        Ok(try!(fs::write(path, self.serialize())))
+    }
+
+    /// Read the serialized fat database from the file.
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<FatDatabase, ScanError> {
+        let bytes = try!(fs::read(path));
+
+        Ok(try!(FatDatabase::deserialize(&bytes)))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_fat_database() {
+        let _ = env_logger::try_init();
+
+        let patterns = patterns!(["foo", "bar"]);
+        let generic = PlatformInfo::new(HS_TUNE_FAMILY_GENERIC, 0);
+        let avx2 = PlatformInfo::new(HS_TUNE_FAMILY_HSW, HS_CPU_FEATURES_AVX2 as u64);
+
+        let fat = FatDatabase::build::<Block>(&patterns, &[generic, avx2]).unwrap();
+        let fat = FatDatabase::deserialize(&fat.serialize()).unwrap();
+
+        assert_eq!(fat.mode(), HS_MODE_BLOCK);
+        assert_eq!(fat.targets().len(), 2);
+        assert_eq!(fat.targets()[1].cpu_features(), HS_CPU_FEATURES_AVX2 as u64);
+
+        // a host without AVX2 gets the generic variant
+        let old = fat.select(&PlatformInfo::new(HS_TUNE_FAMILY_SNB, 0)).unwrap();
+
+        let new = fat
+            .select(&PlatformInfo::new(
+                HS_TUNE_FAMILY_HSW,
+                (HS_CPU_FEATURES_AVX2 | HS_CPU_FEATURES_AVX512) as u64,
+            ))
+            .unwrap();
+
+        assert_ne!(old, new);
+
+        let db = fat.load::<Block>().unwrap();
+        let s = db.alloc().unwrap();
+
+        db.scan::<()>("foo test bar", 0, &s, None, None).unwrap();
+
+        assert_eq!(fat.load::<Streaming>().err(), Some(Error::DbModeError));
+    }
+
+    #[test]
+    fn test_fat_database_invalid() {
+        assert_eq!(FatDatabase::deserialize(b"HSFATDB1").err(), Some(Error::Invalid));
+        assert_eq!(
+            FatDatabase::build::<Block>(&patterns!(["foo"]), &[]).err(),
+            Some(Error::Invalid)
+        );
+    }
+}
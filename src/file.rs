@@ -0,0 +1,362 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+
+use memmap::Mmap;
+
+use api::*;
+use common::{BlockDatabase, StreamingDatabase};
+use errors::{Error, ScanError};
+use lines::{LineCallback, LineScanner};
+
+/// The size of the chunks read from the files which can't be mapped.
+const BUF_SIZE: usize = 64 * 1024;
+
+/// The maximum size of the files which can't be mapped, read into the memory to be scanned as a block.
+const MAX_READ_SIZE: usize = 64 * 1024 * 1024;
+
+/// Definition of the match event callback function type for the scanned files.
+///
+/// It is the same as `MatchEventCallback`, with the path of the file that has matched.
+///
+/// Fn(path: &Path, id: u32, from: u64, to: u64, flags: u32) -> bool
+///
+pub type FileMatchCallback<D> = fn(path: &Path, id: u32, from: u64, to: u64, flags: u32, data: &D) -> u32;
+
+struct FileContext<'a, D: 'a> {
+    path: &'a Path,
+    callback: FileMatchCallback<D>,
+    context: &'a D,
+}
+
+fn on_file_match<D>(id: u32, from: u64, to: u64, flags: u32, ctx: &FileContext<D>) -> u32 {
+    (ctx.callback)(ctx.path, id, from, to, flags, ctx.context)
+}
+
+enum FileData {
+    /// A regular file mapped into the memory.
+    Mapped(Mmap),
+    /// A special file (pipe, device, `/proc` etc) which can't be mapped.
+    Special(File),
+}
+
+fn open_file(path: &Path) -> Result<FileData, ScanError> {
+    let file = try!(File::open(path));
+    let metadata = try!(file.metadata());
+
+    // the files in `/proc` are reported as empty regular files
+    if metadata.is_file() && metadata.len() > 0 {
+        match unsafe { Mmap::map(&file) } {
+            Ok(mmap) => {
+                trace!("mapped {} bytes of file `{}`", mmap.len(), path.display());
+
+                return Ok(FileData::Mapped(mmap));
+            }
+            Err(err) => debug!("fail to map file `{}`, {}", path.display(), err),
+        }
+    }
+
+    debug!("read file `{}` without mapping", path.display());
+
+    Ok(FileData::Special(file))
+}
+
+/// Read the special file to scan it as a block, the files larger than `MAX_READ_SIZE`,
+/// such as an endless pipe, return `Error::TooLarge` after consuming that many bytes.
+fn read_special(file: File) -> Result<Vec<u8>, ScanError> {
+    let mut buf = Vec::new();
+
+    try!(file.take(MAX_READ_SIZE as u64 + 1).read_to_end(&mut buf));
+
+    if buf.len() > MAX_READ_SIZE {
+        return Err(Error::TooLarge(buf.len()).into());
+    }
+
+    Ok(buf)
+}
+
+impl BlockDatabase {
+    /// Memory-map the file and scan it as a block without copying.
+    ///
+    /// The files which can't be mapped, such as the pipes and the `/proc` files, are read
+    /// into the memory before scanning, up to 64 MiB. The larger ones and the files larger
+    /// than 4 GiB can't be scanned as a block and return `Error::TooLarge`,
+    /// use `StreamingDatabase::scan_file` for them, which also reads the pipes in chunks.
+    ///
+    /// Returns the number of bytes scanned.
+    pub fn scan_file<P: AsRef<Path>, S: Scratch, D>(
+        &self,
+        path: P,
+        scratch: &S,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&D>,
+    ) -> Result<u64, ScanError> {
+        let len = match try!(open_file(path.as_ref())) {
+            FileData::Mapped(mmap) => {
+                try!(self.scan(&mmap[..], 0, scratch, callback, context));
+
+                mmap.len()
+            }
+            FileData::Special(file) => {
+                let buf = try!(read_special(file));
+
+                try!(self.scan(&buf[..], 0, scratch, callback, context));
+
+                buf.len()
+            }
+        };
+
+        Ok(len as u64)
+    }
+
+    /// Memory-map the file and scan it as a block, reporting every line with the matches which end in it.
+    ///
+    /// The files which can't be mapped are read into the memory up to 64 MiB like `scan_file`,
+    /// the larger ones and the files larger than 4 GiB return `Error::TooLarge`,
+    /// use `LineScanner::scan_file` for them.
+    ///
+    /// Returns the number of bytes scanned.
+    pub fn scan_file_each_line<P: AsRef<Path>, S: Scratch, D>(
+        &self,
+        path: P,
+        scratch: &S,
+        callback: LineCallback<D>,
+        context: &D,
+    ) -> Result<u64, ScanError> {
+        let len = match try!(open_file(path.as_ref())) {
+            FileData::Mapped(mmap) => {
+                try!(self.scan_each_line(&mmap[..], scratch, callback, context));
+
+                mmap.len()
+            }
+            FileData::Special(file) => {
+                let buf = try!(read_special(file));
+
+                try!(self.scan_each_line(&buf[..], scratch, callback, context));
+
+                buf.len()
+            }
+        };
+
+        Ok(len as u64)
+    }
+
+    /// Scan the files one by one with the same scratch space,
+    /// reporting the path of the file that has matched to the callback.
+    ///
+    /// Each file is scanned like `scan_file`, so the pipes are read up to 64 MiB.
+    ///
+    /// Returns the number of bytes scanned.
+    pub fn scan_files<I, P, S, D>(
+        &self,
+        paths: I,
+        scratch: &S,
+        callback: FileMatchCallback<D>,
+        context: &D,
+    ) -> Result<u64, ScanError>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+        S: Scratch,
+    {
+        let mut total = 0;
+
+        for path in paths {
+            let ctx = FileContext {
+                path: path.as_ref(),
+                callback: callback,
+                context: context,
+            };
+
+            total += try!(self.scan_file(
+                path.as_ref(),
+                scratch,
+                Some(on_file_match::<D> as MatchEventCallback<FileContext<D>>),
+                Some(&ctx),
+            ));
+        }
+
+        Ok(total)
+    }
+}
+
+impl StreamingDatabase {
+    /// Memory-map the file and scan it through a stream without copying.
+    ///
+    /// The files which can't be mapped are read in chunks, and the files larger than 4 GiB
+    /// are scanned in several calls, with the absolute offsets kept in the stream.
+    ///
+    /// Returns the number of bytes scanned.
+    pub fn scan_file<P: AsRef<Path>, S: Scratch, D>(
+        &self,
+        path: P,
+        scratch: &S,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&D>,
+    ) -> Result<u64, ScanError> {
+        match try!(open_file(path.as_ref())) {
+            FileData::Mapped(mmap) => {
+                let stream = try!(self.open_stream(0));
+
+                try!(stream.scan(&mmap[..], 0, scratch, callback, context));
+                try!(stream.close(scratch, callback, context));
+
+                Ok(mmap.len() as u64)
+            }
+            FileData::Special(file) => self.scan_reader_with_scratch(file, BUF_SIZE, scratch, callback, context),
+        }
+    }
+}
+
+impl<'a, D: 'a> LineScanner<'a, D> {
+    /// Memory-map the file and scan it as the next chunk of the stream,
+    /// the files which can't be mapped are read in chunks.
+    ///
+    /// Returns the number of bytes scanned.
+    pub fn scan_file<P: AsRef<Path>>(&mut self, path: P) -> Result<u64, ScanError> {
+        match try!(open_file(path.as_ref())) {
+            FileData::Mapped(mmap) => {
+                try!(self.scan(&mmap[..]));
+
+                Ok(mmap.len() as u64)
+            }
+            FileData::Special(mut file) => {
+                let mut buf = vec![0; BUF_SIZE];
+                let mut total = 0;
+
+                loop {
+                    let n = match file.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => n,
+                        Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+                        Err(err) => return Err(err.into()),
+                    };
+
+                    try!(self.scan(&buf[..n]));
+
+                    total += n as u64;
+                }
+
+                Ok(total)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    use super::super::*;
+    use super::super::harness::on_match;
+
+    fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("hyperscan-{}-{}", process::id(), name));
+
+        File::create(&path).unwrap().write_all(data).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn test_scan_file() {
+        let _ = env_logger::try_init();
+
+        let path = temp_file("scan_file", b"foo test bar");
+        let matches = RefCell::new(Vec::new());
+
+        let db: BlockDatabase = patterns!(["test", "bar$"]).build().unwrap();
+        let s = db.alloc().unwrap();
+
+        assert_eq!(db.scan_file(&path, &s, Some(on_match), Some(&matches)).unwrap(), 12);
+        assert_eq!(*matches.borrow(), vec![(1, 8), (2, 12)]);
+        assert_eq!(db.scan_file::<_, _, ()>("/dev/null", &s, None, None).unwrap(), 0);
+
+        // the endless special files can't be scanned as a block
+        match db.scan_file::<_, _, ()>("/dev/zero", &s, None, None) {
+            Err(ScanError::Scan(Error::TooLarge(_))) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        matches.borrow_mut().clear();
+
+        let db: StreamingDatabase = patterns!(["test", "bar$"]).build().unwrap();
+        let s = db.alloc().unwrap();
+
+        assert_eq!(db.scan_file(&path, &s, Some(on_match), Some(&matches)).unwrap(), 12);
+        assert_eq!(*matches.borrow(), vec![(1, 8), (2, 12)]);
+        assert_eq!(db.scan_file::<_, _, ()>("/dev/null", &s, None, None).unwrap(), 0);
+
+        match db.scan_file::<_, _, ()>("/not/exists", &s, None, None) {
+            Err(ScanError::Io(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_scan_files() {
+        let _ = env_logger::try_init();
+
+        fn callback(path: &Path, id: u32, _: u64, to: u64, _: u32, matches: &RefCell<Vec<(PathBuf, u32, u64)>>) -> u32 {
+            matches.borrow_mut().push((path.to_owned(), id, to));
+
+            0
+        }
+
+        let foo = temp_file("scan_files_foo", b"foo test");
+        let bar = temp_file("scan_files_bar", b"test bar");
+        let matches = RefCell::new(Vec::new());
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        assert_eq!(db.scan_files(&[&foo, &bar], &s, callback, &matches).unwrap(), 16);
+        assert_eq!(*matches.borrow(), vec![(foo.clone(), 0, 8), (bar.clone(), 0, 4)]);
+
+        fs::remove_file(&foo).unwrap();
+        fs::remove_file(&bar).unwrap();
+    }
+
+    #[test]
+    fn test_scan_file_each_line() {
+        let _ = env_logger::try_init();
+
+        fn callback(line: &Line, lines: &RefCell<Vec<(u64, usize)>>) -> u32 {
+            lines.borrow_mut().push((line.number, line.matches.len()));
+
+            0
+        }
+
+        let path = temp_file("scan_file_each_line", b"foo\ntest bar\ntest");
+        let lines = RefCell::new(Vec::new());
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        assert_eq!(db.scan_file_each_line(&path, &s, callback, &lines).unwrap(), 17);
+        assert_eq!(*lines.borrow(), vec![(1, 0), (2, 1), (3, 1)]);
+
+        lines.borrow_mut().clear();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let mut scanner = LineScanner::each_line(&db, callback, &lines).unwrap();
+
+        assert_eq!(scanner.scan_file(&path).unwrap(), 17);
+        assert_eq!(scanner.scan_file("/dev/null").unwrap(), 0);
+
+        scanner.finish().unwrap();
+
+        assert_eq!(*lines.borrow(), vec![(1, 0), (2, 1), (3, 1)]);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
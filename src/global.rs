@@ -0,0 +1,228 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use api::*;
+use common::BlockDatabase;
+use compile::Patterns;
+use errors::Error;
+use runtime::RawScratch;
+
+/// A match reported by `GlobalScanner::find_all`, or with its `Line`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Match {
+    /// The ID number of the expression that matched.
+    pub id: u32,
+    /// The offset of the start of the match,
+    /// only available for the patterns compiled with `HS_FLAG_SOM_LEFTMOST`.
+    pub from: u64,
+    /// The offset after the last byte of the match.
+    pub to: u64,
+}
+
+struct Compiled {
+    /// The unique ID of the compiled database, to find the scratch space of the current thread.
+    id: usize,
+    db: BlockDatabase,
+    /// The scratch space which the scratch space of each thread is cloned from.
+    prototype: Mutex<RawScratch>,
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SCRATCHES: RefCell<HashMap<usize, RawScratch>> = RefCell::new(HashMap::new());
+}
+
+/// A block database compiled from the patterns on the first use, which can be scanned
+/// from any thread without a scratch argument.
+///
+/// Each thread keeps its own scratch space cloned from the first one,
+/// which lives until the thread exits. It is intended to be used as a `static`,
+/// see `lazy_patterns!`.
+pub struct GlobalScanner {
+    patterns: fn() -> Patterns,
+    compiled: OnceLock<Result<Compiled, Error>>,
+}
+
+impl fmt::Debug for GlobalScanner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.compiled.get() {
+            Some(&Ok(ref compiled)) => write!(f, "GlobalScanner{{db: {:?}}}", compiled.db),
+            Some(&Err(ref err)) => write!(f, "GlobalScanner{{err: {:?}}}", err),
+            None => write!(f, "GlobalScanner{{}}"),
+        }
+    }
+}
+
+impl GlobalScanner {
+    /// Construct a scanner which compiles the patterns returned by the function on the first use.
+    pub const fn new(patterns: fn() -> Patterns) -> GlobalScanner {
+        GlobalScanner {
+            patterns: patterns,
+            compiled: OnceLock::new(),
+        }
+    }
+
+    fn compiled(&self) -> Result<&Compiled, Error> {
+        let compiled = self.compiled.get_or_init(|| {
+            let patterns = (self.patterns)();
+            let db: BlockDatabase = try!(patterns.build());
+            let prototype = try!(db.alloc());
+
+            debug!("compiled {} patterns to global {:?}", patterns.len(), db);
+
+            Ok(Compiled {
+                id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+                db: db,
+                prototype: Mutex::new(prototype),
+            })
+        });
+
+        match *compiled {
+            Ok(ref compiled) => Ok(compiled),
+            Err(ref err) => Err(err.clone()),
+        }
+    }
+
+    /// The compiled database, or the error of compiling the patterns.
+    pub fn database(&self) -> Result<&BlockDatabase, Error> {
+        self.compiled().map(|compiled| &compiled.db)
+    }
+
+    /// Scan the data with the scratch space of the current thread.
+    pub fn scan<T: Scannable, D>(
+        &self,
+        data: T,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&D>,
+    ) -> Result<&Self, Error> {
+        let compiled = try!(self.compiled());
+
+        // the scratch space is taken out of the thread local map while scanning,
+        // so the callback can scan with the same scanner again.
+        let scratch = SCRATCHES.with(|scratches| scratches.borrow_mut().remove(&compiled.id));
+        let scratch = match scratch {
+            Some(scratch) => scratch,
+            None => match compiled.prototype.lock() {
+                Ok(prototype) => prototype.clone(),
+                Err(err) => err.into_inner().clone(),
+            },
+        };
+
+        let res = compiled.db.scan(data, 0, &scratch, callback, context);
+
+        SCRATCHES.with(|scratches| scratches.borrow_mut().insert(compiled.id, scratch));
+
+        try!(res);
+
+        Ok(self)
+    }
+
+    /// Whether any pattern matches the data, stopping at the first match.
+    pub fn is_match<T: Scannable>(&self, data: T) -> Result<bool, Error> {
+        fn on_match(_: u32, _: u64, _: u64, _: u32, _: &()) -> u32 {
+            1
+        }
+
+        match self.scan(data, Some(on_match), Some(&())) {
+            Ok(_) => Ok(false),
+            Err(Error::ScanTerminated) => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Find all the matches in the data.
+    pub fn find_all<T: Scannable>(&self, data: T) -> Result<Vec<Match>, Error> {
+        fn on_match(id: u32, from: u64, to: u64, _: u32, matches: &RefCell<Vec<Match>>) -> u32 {
+            matches.borrow_mut().push(Match {
+                id: id,
+                from: from,
+                to: to,
+            });
+
+            0
+        }
+
+        let matches = RefCell::new(Vec::new());
+
+        try!(self.scan(data, Some(on_match), Some(&matches)));
+
+        Ok(matches.into_inner())
+    }
+}
+
+/// Define the `static` global scanners of the patterns, which are compiled on the first use.
+///
+/// ```rust,ignore
+/// lazy_patterns! {
+///     static RULES = patterns!(["foo", "bar"], flags => HS_FLAG_SOM_LEFTMOST);
+/// }
+///
+/// assert!(RULES.is_match("foo test").unwrap());
+/// ```
+#[macro_export]
+macro_rules! lazy_patterns {
+    ($( $(#[$attr:meta])* $vis:vis static $name:ident = $patterns:expr; )*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::GlobalScanner = $crate::GlobalScanner::new({
+                fn patterns() -> $crate::Patterns {
+                    $patterns
+                }
+
+                patterns
+            });
+        )*
+    };
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::thread;
+
+    use super::super::*;
+
+    lazy_patterns! {
+        static RULES = patterns!(["foo", "bar"], flags => HS_FLAG_SOM_LEFTMOST);
+        static INVALID = vec![pattern!{"a++"}];
+    }
+
+    #[test]
+    fn test_global_scanner() {
+        let _ = env_logger::try_init();
+
+        assert!(RULES.is_match("foo test").unwrap());
+        assert!(!RULES.is_match("test").unwrap());
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| thread::spawn(|| RULES.find_all("foo test bar").unwrap()))
+            .collect();
+
+        for t in threads {
+            assert_eq!(
+                t.join().unwrap(),
+                vec![
+                    Match { id: 1, from: 0, to: 3 },
+                    Match { id: 2, from: 9, to: 12 },
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_global_scanner_compile_error() {
+        let _ = env_logger::try_init();
+
+        match INVALID.is_match("test") {
+            Err(Error::CompilerError(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        assert!(INVALID.database().is_err());
+    }
+}
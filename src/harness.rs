@@ -0,0 +1,223 @@
+use std::cell::RefCell;
+
+use any::AnyDatabase;
+use api::*;
+use compile::Patterns;
+use constants::*;
+use errors::Error;
+
+/// The matches reported by a scan, the ID of the expression and the offset after its last byte.
+pub type Matches = Vec<(u32, u64)>;
+
+/// A xorshift64* generator, to split the data reproducibly from a seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // mix the seed, so that the common seeds such as 0 don't start from the zero state
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            0
+        } else {
+            (self.next() % n as u64) as usize
+        }
+    }
+}
+
+/// The sorted offsets to split the data of `len` bytes, into at most `max_cuts + 1` chunks,
+/// which may be empty.
+pub fn random_boundaries(rng: &mut Rng, len: usize, max_cuts: usize) -> Vec<usize> {
+    let cuts = rng.below(max_cuts + 1);
+    let mut boundaries: Vec<usize> = (0..cuts).map(|_| rng.below(len + 1)).collect();
+
+    boundaries.sort();
+    boundaries
+}
+
+/// Split the data at the offsets.
+pub fn split<'a>(data: &'a [u8], boundaries: &[usize]) -> Vec<&'a [u8]> {
+    let mut chunks = Vec::with_capacity(boundaries.len() + 1);
+    let mut start = 0;
+
+    for &off in boundaries {
+        let off = off.max(start).min(data.len());
+
+        chunks.push(&data[start..off]);
+        start = off;
+    }
+
+    chunks.push(&data[start..]);
+    chunks
+}
+
+/// Compile the patterns into a database of the mode.
+///
+/// The streaming databases of the patterns with `HS_FLAG_SOM_LEFTMOST` get the largest SOM horizon.
+pub fn compile(patterns: &Patterns, mode: u32) -> Result<AnyDatabase, Error> {
+    match mode {
+        HS_MODE_BLOCK => patterns.build().map(AnyDatabase::Block),
+        HS_MODE_STREAM => patterns.build().map(AnyDatabase::Streaming),
+        HS_MODE_VECTORED => patterns.build().map(AnyDatabase::Vectored),
+        _ => Err(Error::DbModeError),
+    }
+}
+
+pub fn mode_name(mode: u32) -> &'static str {
+    match mode {
+        HS_MODE_BLOCK => "block",
+        HS_MODE_STREAM => "streaming",
+        HS_MODE_VECTORED => "vectored",
+        _ => "unknown",
+    }
+}
+
+/// Collect the ID and the end offset of the matches, also shared by the tests.
+pub fn on_match(id: u32, _: u64, to: u64, _: u32, matches: &RefCell<Matches>) -> u32 {
+    matches.borrow_mut().push((id, to));
+
+    0
+}
+
+/// Stop the scan at the first match.
+#[cfg(test)]
+pub fn stop_on_match<D>(_: u32, _: u64, _: u64, _: u32, _: &D) -> u32 {
+    1
+}
+
+/// Scan the data split at the offsets, as the segments of a vectored scan or the chunks of a stream,
+/// the block databases scan the whole data.
+///
+/// With a `limit`, the segments and chunks are further split by the wrapper into the parts
+/// of at most `limit` bytes, like the data too large to be passed to Hyperscan at once.
+pub fn scan(db: &AnyDatabase, data: &[u8], boundaries: &[usize], limit: Option<usize>) -> Result<Matches, Error> {
+    let matches = RefCell::new(Vec::new());
+
+    match *db {
+        AnyDatabase::Block(ref db) => {
+            let s = try!(db.alloc());
+
+            try!(db.scan(data, 0, &s, Some(on_match), Some(&matches)));
+        }
+        AnyDatabase::Vectored(ref db) => {
+            let s = try!(db.alloc());
+            let segments = split(data, boundaries);
+
+            match limit {
+                Some(limit) => try!(db.scan_vector(&segments, limit, 0, &s, Some(on_match), Some(&matches))),
+                None => try!(db.scan(&segments, 0, &s, Some(on_match), Some(&matches))),
+            };
+        }
+        AnyDatabase::Streaming(ref db) => {
+            let s = try!(db.alloc());
+            let stream = try!(db.open_stream(0));
+
+            for chunk in split(data, boundaries) {
+                match limit {
+                    Some(limit) => try!(stream.scan_chunks(chunk, limit, 0, &s, Some(on_match), Some(&matches))),
+                    None => try!(stream.scan(chunk, 0, &s, Some(on_match), Some(&matches))),
+                };
+            }
+
+            try!(stream.close(&s, Some(on_match), Some(&matches)));
+        }
+    }
+
+    Ok(matches.into_inner())
+}
+
+/// Remove the bytes from the data while the predicate holds, moving the boundaries with them.
+///
+/// At most `attempts` candidates are tried, returns the smallest data and boundaries found.
+#[cfg(any(test, feature = "regex"))]
+pub fn minimize<F>(data: &[u8], boundaries: &[usize], attempts: usize, mut predicate: F) -> (Vec<u8>, Vec<usize>)
+where
+    F: FnMut(&[u8], &[usize]) -> bool,
+{
+    let mut data = data.to_vec();
+    let mut boundaries = boundaries.to_vec();
+    let mut chunk = data.len() / 2;
+    let mut attempts = attempts;
+
+    while chunk > 0 && attempts > 0 {
+        let mut start = 0;
+        let mut removed = false;
+
+        while start < data.len() && attempts > 0 {
+            let end = (start + chunk).min(data.len());
+            let mut candidate = data[..start].to_vec();
+
+            candidate.extend_from_slice(&data[end..]);
+
+            let moved: Vec<usize> = boundaries
+                .iter()
+                .map(|&off| {
+                    if off <= start {
+                        off
+                    } else {
+                        off.max(end) - (end - start)
+                    }
+                })
+                .collect();
+
+            attempts -= 1;
+
+            if predicate(&candidate, &moved) {
+                data = candidate;
+                boundaries = moved;
+                removed = true;
+            } else {
+                start = end;
+            }
+        }
+
+        if !removed {
+            chunk /= 2;
+        }
+    }
+
+    (data, boundaries)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let data = b"hello world";
+
+        assert_eq!(split(data, &[]), vec![&data[..]]);
+        assert_eq!(
+            split(data, &[0, 5, 5, 20]),
+            vec![&b""[..], &b"hello"[..], &b""[..], &b" world"[..], &b""[..]]
+        );
+
+        let mut rng = Rng::new(0);
+        let boundaries = random_boundaries(&mut rng, data.len(), 4);
+
+        assert!(boundaries.len() <= 4);
+        assert!(boundaries.iter().all(|&off| off <= data.len()));
+        assert_eq!(split(data, &boundaries).concat(), &data[..]);
+    }
+
+    #[test]
+    fn test_minimize() {
+        let (data, boundaries) = minimize(b"xxxxfooxxxx", &[2, 5, 9], 1000, |data, _| {
+            data.windows(3).any(|w| w == b"foo")
+        });
+
+        assert_eq!(data, b"foo");
+        assert_eq!(boundaries, vec![0, 1, 3]);
+    }
+}
@@ -30,8 +30,18 @@
 
 #[macro_use]
 extern crate log;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "ed25519-dalek")]
+extern crate ed25519_dalek;
 extern crate libc;
+extern crate memmap;
+#[cfg(any(test, feature = "regex"))]
+extern crate regex;
 extern crate regex_syntax;
+extern crate sha2;
+#[cfg(feature = "tokio")]
+extern crate tokio;
 
 extern crate hyperscan_sys as raw;
 
@@ -39,21 +49,66 @@ mod constants;
 mod cptr;
 #[macro_use]
 mod errors;
+mod alloc;
+mod memory;
 mod api;
+mod any;
 mod common;
+mod consistency;
+#[cfg(any(test, feature = "regex"))]
+mod differential;
+mod buffer;
+mod bundle;
+mod codec;
+mod embed;
+mod envelope;
+mod fat;
 #[macro_use]
 mod compile;
 mod runtime;
+mod file;
+mod global;
+mod harness;
+mod lines;
+mod segments;
+#[cfg(unix)]
+mod shared;
+mod table;
+mod reader;
+mod writer;
+mod version;
+#[cfg(feature = "tokio")]
+mod async_io;
 
+pub use alloc::{set_allocator, set_database_allocator, set_misc_allocator, set_scratch_allocator,
+                set_stream_allocator, Allocator, GlobalAllocator, SystemAllocator};
+pub use any::{AnyDatabase, AnySerializedDatabase};
 pub use api::*;
+pub use buffer::{DatabaseBuffer, DatabaseRef};
+pub use bundle::DatabaseBundle;
+pub use embed::{embed_database, embed_database_to, parse_patterns};
+pub use envelope::DatabaseEnvelope;
+pub use fat::FatDatabase;
 pub use common::{BlockDatabase, RawDatabase, StreamingDatabase, VectoredDatabase};
 pub use compile::{CompileFlags, Pattern, Patterns};
+pub use consistency::{verify_consistency, ConsistencyReport, Inconsistency};
+#[cfg(any(test, feature = "regex"))]
+pub use differential::{DifferentialChecker, DifferentialReport, Divergence};
 pub use constants::*;
-pub use errors::Error;
+pub use errors::{Error, ScanError, SharedError};
+pub use file::FileMatchCallback;
+pub use global::{GlobalScanner, Match};
+pub use memory::{assert_no_leaks, enable_memory_stats, memory_stats, MemoryCategory, MemoryStats, MemoryUsage};
+pub use lines::{Line, LineCallback, LineMatch, LineMatchCallback, LineScanner};
 pub use runtime::{RawScratch, RawStream};
-
-#[cfg(test)]
-extern crate regex;
+pub use segments::{SegmentOffset, Segments};
+#[cfg(unix)]
+pub use shared::SharedDatabase;
+pub use table::{FlowMatchCallback, StreamTable};
+pub use version::{check_compatibility, version, Version};
+pub use writer::StreamWriter;
+#[cfg(feature = "tokio")]
+pub use async_io::{AsyncStreamScanner, ScanAsyncReader};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,546 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+
+use api::*;
+use common::{BlockDatabase, StreamingDatabase};
+use errors::Error;
+use global::Match;
+use runtime::{RawScratch, RawStream};
+
+/// A match with the line which contains it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineMatch<'a> {
+    /// The ID number of the expression that matched.
+    pub id: u32,
+    /// The absolute offset of the start of the match,
+    /// only available for the patterns compiled with `HS_FLAG_SOM_LEFTMOST`.
+    pub from: u64,
+    /// The absolute offset after the last byte of the match.
+    pub to: u64,
+    /// The flags of the match.
+    pub flags: u32,
+    /// The 1-based number of the line which contains the last byte of the match.
+    pub line: u64,
+    /// The 1-based byte column of the start of the match in the line,
+    /// or 1 if the match starts in a previous line.
+    pub column: u64,
+    /// The absolute byte range of the line, without the line terminator.
+    pub range: Range<u64>,
+    /// The text of the line, without the line terminator.
+    pub text: &'a [u8],
+}
+
+/// Definition of the line match callback function type.
+///
+/// Returning a non-zero value stops reporting the matches,
+/// and the scan returns `Error::ScanTerminated`.
+pub type LineMatchCallback<D> = fn(m: &LineMatch, data: &D) -> u32;
+
+/// A line with the matches which end in it, reported for every line including the lines without any match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line<'a> {
+    /// The 1-based number of the line.
+    pub number: u64,
+    /// The absolute byte range of the line, without the line terminator.
+    pub range: Range<u64>,
+    /// The text of the line, without the line terminator.
+    pub text: &'a [u8],
+    /// The matches whose last byte is in the line, ordered by their end offset.
+    pub matches: &'a [Match],
+}
+
+/// Definition of the line callback function type.
+///
+/// Returning a non-zero value stops reporting the lines,
+/// and the scan returns `Error::ScanTerminated`.
+pub type LineCallback<D> = fn(line: &Line, data: &D) -> u32;
+
+/// How the lines are reported to the callback.
+enum Report<D> {
+    /// Report each match with its line, only the first match of each line with `dedup`.
+    Matches {
+        dedup: bool,
+        callback: LineMatchCallback<D>,
+    },
+    /// Report every line with its matches.
+    Lines(LineCallback<D>),
+}
+
+impl<D> Clone for Report<D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D> Copy for Report<D> {}
+
+#[derive(Debug, Clone, Copy)]
+struct RawMatch {
+    id: u32,
+    from: u64,
+    to: u64,
+    flags: u32,
+}
+
+impl RawMatch {
+    // the offset of the last byte of the match, which decides its line
+    fn last_byte(&self) -> u64 {
+        if self.to > self.from {
+            self.to - 1
+        } else {
+            self.to
+        }
+    }
+}
+
+fn on_raw_match(id: u32, from: u64, to: u64, flags: u32, matches: &RefCell<Vec<RawMatch>>) -> u32 {
+    matches.borrow_mut().push(RawMatch {
+        id: id,
+        from: from,
+        to: to,
+        flags: flags,
+    });
+
+    0
+}
+
+/// Split the data into lines, and report the pending matches with their lines.
+struct LineTracker {
+    terminated: bool,
+    /// The number of the current line.
+    line: u64,
+    /// The absolute offset of the current line.
+    line_start: u64,
+    /// The bytes of the current line which have been scanned in the previous chunks.
+    partial: Vec<u8>,
+    /// The matches which haven't been reported.
+    pending: RefCell<Vec<RawMatch>>,
+    /// The matches of the reported line, kept to reuse the allocation.
+    line_matches: Vec<Match>,
+}
+
+impl LineTracker {
+    fn new() -> LineTracker {
+        LineTracker {
+            terminated: false,
+            line: 1,
+            line_start: 0,
+            partial: Vec::new(),
+            pending: RefCell::new(Vec::new()),
+            line_matches: Vec::new(),
+        }
+    }
+
+    /// Report the pending matches in the lines completed by the chunk, and keep the incomplete line.
+    fn feed<D>(&mut self, chunk: &[u8], report: Report<D>, context: &D) -> Result<(), Error> {
+        let mut pending = mem::replace(&mut *self.pending.borrow_mut(), Vec::new());
+
+        pending.sort_by_key(|m| m.to);
+
+        let mut start = 0;
+
+        for (off, _) in chunk.iter().enumerate().filter(|&(_, &b)| b == b'\n') {
+            let res = if self.partial.is_empty() {
+                self.report_line(&chunk[start..off + 1], &mut pending, report, context)
+            } else {
+                let mut line = mem::replace(&mut self.partial, Vec::new());
+
+                line.extend_from_slice(&chunk[start..off + 1]);
+
+                let res = self.report_line(&line, &mut pending, report, context);
+
+                line.clear();
+                self.partial = line;
+
+                res
+            };
+
+            try!(res);
+
+            start = off + 1;
+        }
+
+        self.partial.extend_from_slice(&chunk[start..]);
+
+        *self.pending.borrow_mut() = pending;
+
+        Ok(())
+    }
+
+    /// Report the remaining matches with the last line.
+    fn finish<D>(&mut self, report: Report<D>, context: &D) -> Result<(), Error> {
+        let mut pending = mem::replace(&mut *self.pending.borrow_mut(), Vec::new());
+
+        pending.sort_by_key(|m| m.to);
+
+        let line = mem::replace(&mut self.partial, Vec::new());
+
+        if !line.is_empty() || !pending.is_empty() {
+            try!(self.report_line(&line, &mut pending, report, context));
+        }
+
+        Ok(())
+    }
+
+    /// Report the pending matches which end in the line, and move to the next line.
+    fn report_line<D>(
+        &mut self,
+        line: &[u8],
+        pending: &mut Vec<RawMatch>,
+        report: Report<D>,
+        context: &D,
+    ) -> Result<(), Error> {
+        let line_end = self.line_start + line.len() as u64;
+        let mut text = line;
+
+        if text.ends_with(b"\n") {
+            text = &text[..text.len() - 1];
+        }
+        if text.ends_with(b"\r") {
+            text = &text[..text.len() - 1];
+        }
+
+        let n = pending
+            .iter()
+            .take_while(|m| m.last_byte() < line_end || line.last() != Some(&b'\n'))
+            .count();
+
+        match report {
+            Report::Matches { dedup, callback } => {
+                for (i, m) in pending.drain(..n).enumerate() {
+                    if self.terminated || (dedup && i > 0) {
+                        continue;
+                    }
+
+                    let line_match = LineMatch {
+                        id: m.id,
+                        from: m.from,
+                        to: m.to,
+                        flags: m.flags,
+                        line: self.line,
+                        column: m.from.max(self.line_start) - self.line_start + 1,
+                        range: self.line_start..self.line_start + text.len() as u64,
+                        text: text,
+                    };
+
+                    if callback(&line_match, context) != 0 {
+                        self.terminated = true;
+                    }
+                }
+            }
+            Report::Lines(callback) => {
+                let mut matches = mem::replace(&mut self.line_matches, Vec::new());
+
+                matches.clear();
+                matches.extend(pending.drain(..n).map(|m| Match {
+                    id: m.id,
+                    from: m.from,
+                    to: m.to,
+                }));
+
+                if !self.terminated {
+                    let line = Line {
+                        number: self.line,
+                        range: self.line_start..self.line_start + text.len() as u64,
+                        text: text,
+                        matches: &matches,
+                    };
+
+                    if callback(&line, context) != 0 {
+                        self.terminated = true;
+                    }
+                }
+
+                self.line_matches = matches;
+            }
+        }
+
+        self.line += 1;
+        self.line_start = line_end;
+
+        if self.terminated {
+            Err(Error::ScanTerminated)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl BlockDatabase {
+    /// Scan the data as a block, and report the matches with the line which contains them.
+    ///
+    /// The lines are terminated by `\n` or `\r\n`, and only the first match of each line
+    /// is reported when `dedup` is set.
+    pub fn scan_lines<T: Scannable, S: Scratch, D>(
+        &self,
+        data: T,
+        scratch: &S,
+        dedup: bool,
+        callback: LineMatchCallback<D>,
+        context: &D,
+    ) -> Result<&Self, Error> {
+        self.scan_with_report(
+            data.as_bytes(),
+            scratch,
+            Report::Matches {
+                dedup: dedup,
+                callback: callback,
+            },
+            context,
+        )
+    }
+
+    /// Scan the data as a block, and report every line with the matches which end in it,
+    /// such as to print the lines around the matching lines.
+    pub fn scan_each_line<T: Scannable, S: Scratch, D>(
+        &self,
+        data: T,
+        scratch: &S,
+        callback: LineCallback<D>,
+        context: &D,
+    ) -> Result<&Self, Error> {
+        self.scan_with_report(data.as_bytes(), scratch, Report::Lines(callback), context)
+    }
+
+    fn scan_with_report<S: Scratch, D>(
+        &self,
+        bytes: &[u8],
+        scratch: &S,
+        report: Report<D>,
+        context: &D,
+    ) -> Result<&Self, Error> {
+        let mut tracker = LineTracker::new();
+
+        try!(self.scan(bytes, 0, scratch, Some(on_raw_match), Some(&tracker.pending)));
+        try!(tracker.feed(bytes, report, context));
+        try!(tracker.finish(report, context));
+
+        Ok(self)
+    }
+}
+
+/// A stream which reports the matches with the line which contains them.
+///
+/// The lines can cross the chunk boundaries, so the matches are reported
+/// when their line is completed by a later chunk or the stream is finished.
+pub struct LineScanner<'a, D: 'a> {
+    stream: RawStream,
+    scratch: RawScratch,
+    tracker: LineTracker,
+    report: Report<D>,
+    context: &'a D,
+}
+
+impl<'a, D: 'a> fmt::Debug for LineScanner<'a, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "LineScanner{{stream: {:?}, scratch: {:?}, line: {}}}",
+            self.stream, self.scratch, self.tracker.line
+        )
+    }
+}
+
+impl<'a, D: 'a> LineScanner<'a, D> {
+    /// Open a stream against the database to scan the lines.
+    ///
+    /// Only the first match of each line is reported when `dedup` is set.
+    pub fn new(
+        db: &StreamingDatabase,
+        dedup: bool,
+        callback: LineMatchCallback<D>,
+        context: &'a D,
+    ) -> Result<LineScanner<'a, D>, Error> {
+        LineScanner::with_report(
+            db,
+            Report::Matches {
+                dedup: dedup,
+                callback: callback,
+            },
+            context,
+        )
+    }
+
+    /// Open a stream against the database to report every line with the matches which end in it.
+    pub fn each_line(
+        db: &StreamingDatabase,
+        callback: LineCallback<D>,
+        context: &'a D,
+    ) -> Result<LineScanner<'a, D>, Error> {
+        LineScanner::with_report(db, Report::Lines(callback), context)
+    }
+
+    fn with_report(db: &StreamingDatabase, report: Report<D>, context: &'a D) -> Result<LineScanner<'a, D>, Error> {
+        Ok(LineScanner {
+            stream: try!(db.open_stream(0)),
+            scratch: try!(db.alloc()),
+            tracker: LineTracker::new(),
+            report: report,
+            context: context,
+        })
+    }
+
+    /// Scan a chunk of data, reporting the matches in the completed lines.
+    pub fn scan<T: Scannable>(&mut self, data: T) -> Result<&Self, Error> {
+        let bytes = data.as_bytes();
+
+        try!(self.stream.scan(
+            bytes,
+            0,
+            &self.scratch,
+            Some(on_raw_match),
+            Some(&self.tracker.pending)
+        ));
+        try!(self.tracker.feed(bytes, self.report, self.context));
+
+        Ok(self)
+    }
+
+    /// Close the stream, reporting the remaining matches with the last line.
+    pub fn finish(mut self) -> Result<(), Error> {
+        try!(
+            self.stream
+                .close(&self.scratch, Some(on_raw_match), Some(&self.tracker.pending))
+        );
+
+        self.tracker.finish(self.report, self.context)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+
+    use super::super::*;
+
+    fn callback(m: &LineMatch, matches: &RefCell<Vec<(u32, u64, u64, String)>>) -> u32 {
+        matches.borrow_mut().push((
+            m.id,
+            m.line,
+            m.column,
+            String::from_utf8(m.text.to_vec()).unwrap(),
+        ));
+
+        0
+    }
+
+    fn line(id: u32, line: u64, column: u64, text: &str) -> (u32, u64, u64, String) {
+        (id, line, column, text.to_owned())
+    }
+
+    #[test]
+    fn test_block_scan_lines() {
+        let _ = env_logger::try_init();
+
+        let db: BlockDatabase = patterns!(["foo", "bar"], flags => HS_FLAG_SOM_LEFTMOST)
+            .build()
+            .unwrap();
+        let s = db.alloc().unwrap();
+        let data = "foo bar\r\nnothing\n\nbar foo foo";
+
+        let matches = RefCell::new(Vec::new());
+
+        db.scan_lines(data, &s, false, callback, &matches).unwrap();
+
+        assert_eq!(
+            *matches.borrow(),
+            vec![
+                line(1, 1, 1, "foo bar"),
+                line(2, 1, 5, "foo bar"),
+                line(2, 4, 1, "bar foo foo"),
+                line(1, 4, 5, "bar foo foo"),
+                line(1, 4, 9, "bar foo foo"),
+            ]
+        );
+
+        matches.borrow_mut().clear();
+
+        db.scan_lines(data, &s, true, callback, &matches).unwrap();
+
+        assert_eq!(
+            *matches.borrow(),
+            vec![line(1, 1, 1, "foo bar"), line(2, 4, 1, "bar foo foo")]
+        );
+    }
+
+    #[test]
+    fn test_stream_scan_lines() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = patterns!(["foo", "bar$"], flags => HS_FLAG_SOM_LEFTMOST)
+            .build()
+            .unwrap();
+
+        let matches = RefCell::new(Vec::new());
+        let mut scanner = LineScanner::new(&db, false, callback, &matches).unwrap();
+
+        scanner.scan("test f").unwrap();
+        scanner.scan("oo te").unwrap();
+
+        assert!(matches.borrow().is_empty());
+
+        scanner.scan("st\r\nsecond line").unwrap();
+
+        assert_eq!(*matches.borrow(), vec![line(1, 1, 6, "test foo test")]);
+
+        scanner.scan(" foo\nbar").unwrap();
+        scanner.finish().unwrap();
+
+        assert_eq!(
+            *matches.borrow(),
+            vec![
+                line(1, 1, 6, "test foo test"),
+                line(1, 2, 13, "second line foo"),
+                line(2, 3, 1, "bar"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_each_line() {
+        let _ = env_logger::try_init();
+
+        fn callback(line: &Line, lines: &RefCell<Vec<(u64, String, Vec<u32>)>>) -> u32 {
+            lines.borrow_mut().push((
+                line.number,
+                String::from_utf8(line.text.to_vec()).unwrap(),
+                line.matches.iter().map(|m| m.id).collect(),
+            ));
+
+            0
+        }
+
+        let expected = vec![
+            (1, "foo bar".to_owned(), vec![1, 2]),
+            (2, "nothing".to_owned(), vec![]),
+            (3, "".to_owned(), vec![]),
+            (4, "bar".to_owned(), vec![2]),
+        ];
+        let data = "foo bar\r\nnothing\n\nbar";
+
+        let db: BlockDatabase = patterns!(["foo", "bar"]).build().unwrap();
+        let s = db.alloc().unwrap();
+        let lines = RefCell::new(Vec::new());
+
+        db.scan_each_line(data, &s, callback, &lines).unwrap();
+
+        assert_eq!(*lines.borrow(), expected);
+
+        let db: StreamingDatabase = patterns!(["foo", "bar"]).build().unwrap();
+        let lines = RefCell::new(Vec::new());
+        let mut scanner = LineScanner::each_line(&db, callback, &lines).unwrap();
+
+        scanner.scan(&data[..5]).unwrap();
+        scanner.scan(&data[5..]).unwrap();
+
+        assert_eq!(lines.borrow().len(), 3);
+
+        scanner.finish().unwrap();
+
+        assert_eq!(*lines.borrow(), expected);
+    }
+}
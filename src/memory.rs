@@ -0,0 +1,304 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use alloc::set_hooks;
+use errors::Error;
+
+/// The category of the memory allocated by Hyperscan.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    /// The database bytecode.
+    Database,
+    /// The scratch space.
+    Scratch,
+    /// The stream state.
+    Stream,
+    /// The other items returned by the Hyperscan API.
+    Misc,
+}
+
+const CATEGORIES: [MemoryCategory; 4] = [
+    MemoryCategory::Database,
+    MemoryCategory::Scratch,
+    MemoryCategory::Stream,
+    MemoryCategory::Misc,
+];
+
+impl fmt::Display for MemoryCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                MemoryCategory::Database => "database",
+                MemoryCategory::Scratch => "scratch",
+                MemoryCategory::Stream => "stream",
+                MemoryCategory::Misc => "misc",
+            }
+        )
+    }
+}
+
+/// The memory usage of a category.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The bytes allocated and not freed yet.
+    pub bytes: usize,
+    /// The number of the allocations not freed yet.
+    pub allocations: usize,
+    /// The total number of the allocations.
+    pub total_allocations: usize,
+}
+
+/// A snapshot of the memory allocated by Hyperscan.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// The memory used by the database bytecode.
+    pub database: MemoryUsage,
+    /// The memory used by the scratch space.
+    pub scratch: MemoryUsage,
+    /// The memory used by the stream state.
+    pub stream: MemoryUsage,
+    /// The memory used by the other items returned by the Hyperscan API.
+    pub misc: MemoryUsage,
+}
+
+impl MemoryStats {
+    /// The memory usage of a category.
+    pub fn usage(&self, category: MemoryCategory) -> &MemoryUsage {
+        match category {
+            MemoryCategory::Database => &self.database,
+            MemoryCategory::Scratch => &self.scratch,
+            MemoryCategory::Stream => &self.stream,
+            MemoryCategory::Misc => &self.misc,
+        }
+    }
+
+    fn usage_mut(&mut self, category: MemoryCategory) -> &mut MemoryUsage {
+        match category {
+            MemoryCategory::Database => &mut self.database,
+            MemoryCategory::Scratch => &mut self.scratch,
+            MemoryCategory::Stream => &mut self.stream,
+            MemoryCategory::Misc => &mut self.misc,
+        }
+    }
+
+    /// The bytes allocated and not freed yet in all the categories.
+    pub fn bytes(&self) -> usize {
+        self.database.bytes + self.scratch.bytes + self.stream.bytes + self.misc.bytes
+    }
+
+    /// The number of the allocations not freed yet in all the categories.
+    pub fn allocations(&self) -> usize {
+        self.database.allocations + self.scratch.allocations + self.stream.allocations + self.misc.allocations
+    }
+}
+
+struct Allocation {
+    category: MemoryCategory,
+    size: usize,
+    /// The leak check scope which made the allocation, or zero.
+    scope: usize,
+}
+
+struct Tracker {
+    stats: MemoryStats,
+    live: Option<HashMap<usize, Allocation>>,
+}
+
+static TRACKER: Mutex<Tracker> = Mutex::new(Tracker {
+    stats: MemoryStats {
+        database: MemoryUsage {
+            bytes: 0,
+            allocations: 0,
+            total_allocations: 0,
+        },
+        scratch: MemoryUsage {
+            bytes: 0,
+            allocations: 0,
+            total_allocations: 0,
+        },
+        stream: MemoryUsage {
+            bytes: 0,
+            allocations: 0,
+            total_allocations: 0,
+        },
+        misc: MemoryUsage {
+            bytes: 0,
+            allocations: 0,
+            total_allocations: 0,
+        },
+    },
+    live: None,
+});
+
+/// Whether the accounting is enabled, so the allocations through a custom allocator
+/// don't take the lock of the tracker until `enable_memory_stats` is called.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static NEXT_SCOPE: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    static SCOPE: Cell<usize> = Cell::new(0);
+}
+
+fn tracker() -> MutexGuard<'static, Tracker> {
+    match TRACKER.lock() {
+        Ok(tracker) => tracker,
+        Err(err) => err.into_inner(),
+    }
+}
+
+/// Record an allocation made through the hooks.
+pub fn on_alloc(category: MemoryCategory, p: usize, size: usize) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let scope = SCOPE.with(|scope| scope.get());
+    let mut tracker = tracker();
+
+    {
+        let usage = tracker.stats.usage_mut(category);
+
+        usage.bytes += size;
+        usage.allocations += 1;
+        usage.total_allocations += 1;
+    }
+
+    tracker.live.get_or_insert_with(HashMap::new).insert(
+        p,
+        Allocation {
+            category: category,
+            size: size,
+            scope: scope,
+        },
+    );
+}
+
+/// Record a free made through the hooks.
+///
+/// The memory allocated before the hooks were set is unknown and ignored.
+pub fn on_free(p: usize) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut tracker = tracker();
+
+    if let Some(allocation) = tracker.live.as_mut().and_then(|live| live.remove(&p)) {
+        let usage = tracker.stats.usage_mut(allocation.category);
+
+        usage.bytes -= allocation.size;
+        usage.allocations -= 1;
+    }
+}
+
+/// Account the memory allocated by Hyperscan from now on.
+///
+/// Setting a custom allocator doesn't enable the accounting.
+pub fn enable_memory_stats() -> Result<(), Error> {
+    try!(set_hooks());
+
+    ENABLED.store(true, Ordering::SeqCst);
+
+    debug!("enabled memory accounting of Hyperscan");
+
+    Ok(())
+}
+
+/// Take a snapshot of the memory allocated by Hyperscan since the accounting was enabled.
+pub fn memory_stats() -> MemoryStats {
+    tracker().stats
+}
+
+/// Run the closure and panic if any memory allocated by Hyperscan in it
+/// (such as an unclosed `RawStream` or a forgotten database) wasn't freed at the end.
+///
+/// Only the allocations made by the current thread are checked,
+/// so the leak checks of the concurrent tests don't interfere with each other.
+pub fn assert_no_leaks<F: FnOnce() -> R, R>(f: F) -> R {
+    enable_memory_stats().expect("enable memory accounting");
+
+    let scope = NEXT_SCOPE.fetch_add(1, Ordering::SeqCst);
+    let outer = SCOPE.with(|s| s.replace(scope));
+
+    let result = f();
+
+    SCOPE.with(|s| s.set(outer));
+
+    let mut leaks = MemoryStats::default();
+
+    if let Some(ref live) = tracker().live {
+        for allocation in live.values().filter(|allocation| allocation.scope == scope) {
+            let usage = leaks.usage_mut(allocation.category);
+
+            usage.bytes += allocation.size;
+            usage.allocations += 1;
+        }
+    }
+
+    if leaks.allocations() > 0 {
+        let categories: Vec<String> = CATEGORIES
+            .iter()
+            .filter(|&&category| leaks.usage(category).allocations > 0)
+            .map(|&category| {
+                let usage = leaks.usage(category);
+
+                format!("{} bytes in {} {} allocations", usage.bytes, usage.allocations, category)
+            })
+            .collect();
+
+        panic!("leaked Hyperscan memory: {}", categories.join(", "));
+    }
+
+    result
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::mem;
+
+    use super::super::*;
+
+    #[test]
+    fn test_memory_stats() {
+        let _ = env_logger::try_init();
+
+        enable_memory_stats().unwrap();
+
+        assert_no_leaks(|| {
+            let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+
+            assert!(memory_stats().database.bytes >= db.database_size().unwrap());
+
+            let s = db.alloc().unwrap();
+
+            assert!(memory_stats().scratch.bytes >= s.size().unwrap());
+
+            let stream = db.open_stream(0).unwrap();
+
+            assert!(memory_stats().stream.bytes >= db.stream_size().unwrap());
+            assert!(memory_stats().stream.allocations > 0);
+
+            stream.close::<()>(&s, None, None).unwrap();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "leaked Hyperscan memory")]
+    fn test_leaked_stream() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+
+        assert_no_leaks(|| {
+            mem::forget(db.open_stream(0).unwrap());
+        });
+    }
+}
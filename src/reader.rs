@@ -0,0 +1,106 @@
+use std::io::{ErrorKind, Read};
+
+use api::*;
+use common::StreamingDatabase;
+use errors::{Error, ScanError};
+
+impl StreamingDatabase {
+    /// Scan the data pulled from the reader in chunks of `buf_size` bytes through a stream,
+    /// and close the stream at the end of the data.
+    ///
+    /// The match offsets are absolute positions from the start of the reader.
+    ///
+    /// Returns the number of bytes scanned.
+    pub fn scan_reader<R: Read, D>(
+        &self,
+        reader: R,
+        buf_size: usize,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&D>,
+    ) -> Result<u64, ScanError> {
+        let scratch = try!(self.alloc());
+
+        self.scan_reader_with_scratch(reader, buf_size, &scratch, callback, context)
+    }
+
+    /// Scan the data pulled from the reader through a stream like `scan_reader`,
+    /// with a scratch space allocated by the caller.
+    pub fn scan_reader_with_scratch<R: Read, S: Scratch, D>(
+        &self,
+        mut reader: R,
+        buf_size: usize,
+        scratch: &S,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&D>,
+    ) -> Result<u64, ScanError> {
+        if buf_size == 0 {
+            return Err(ScanError::Scan(Error::Invalid));
+        }
+
+        let stream = try!(self.open_stream(0));
+        let mut buf = vec![0; buf_size];
+        let mut total: u64 = 0;
+
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            try!(stream.scan(&buf[..n], 0, scratch, callback, context));
+
+            total += n as u64;
+        }
+
+        try!(stream.close(scratch, callback, context));
+
+        debug!("scanned {} bytes from reader with {} database", total, self.database_name());
+
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+    use std::io::{self, Read};
+
+    use super::super::*;
+    use super::super::harness::on_match;
+
+    struct BrokenReader;
+
+    impl Read for BrokenReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "broken"))
+        }
+    }
+
+    #[test]
+    fn test_scan_reader() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = patterns!(["test", "bar$"]).build().unwrap();
+
+        let matches = RefCell::new(Vec::new());
+        let data = "foo test bar test bar";
+
+        assert_eq!(
+            db.scan_reader(io::Cursor::new(data), 3, Some(on_match), Some(&matches))
+                .unwrap(),
+            data.len() as u64
+        );
+        assert_eq!(*matches.borrow(), vec![(1, 8), (1, 17), (2, 21)]);
+
+        match db.scan_reader::<_, ()>(BrokenReader, 16, None, None) {
+            Err(ScanError::Io(ref err)) => assert_eq!(err.kind(), io::ErrorKind::Other),
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        assert!(db.scan_reader::<_, ()>(io::empty(), 0, None, None).is_err());
+    }
+}
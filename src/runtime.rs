@@ -9,6 +9,9 @@ use common::{BlockDatabase, RawDatabase, StreamingDatabase, VectoredDatabase};
 use errors::Error;
 use raw::*;
 
+/// The maximum length of data which can be passed to Hyperscan in one call.
+const MAX_SCAN_LEN: usize = c_uint::max_value() as usize;
+
 /// A large enough region of scratch space to support a given database.
 ///
 pub struct RawScratch(RawScratchPtr);
@@ -43,6 +46,9 @@ impl RawScratch {
     }
 }
 
+// The scratch space can be moved to another thread, but must not be used concurrently.
+unsafe impl Send for RawScratch {}
+
 impl Drop for RawScratch {
     #[inline]
     fn drop(&mut self) {
@@ -135,36 +141,43 @@ impl<T: Scannable, S: Scratch> BlockScanner<T, S> for BlockDatabase {
         callback: Option<MatchEventCallback<D>>,
         context: Option<&D>,
     ) -> Result<&Self, Error> {
-        unsafe {
-            let bytes = data.as_bytes();
+        let bytes = data.as_bytes();
 
+        // a block can't be split without losing the matches across the boundaries
+        if bytes.len() > MAX_SCAN_LEN {
+            return Err(Error::TooLarge(bytes.len()));
+        }
+
+        unsafe {
             check_hs_error!(hs_scan(
                 **self,
                 bytes.as_ptr() as *const i8,
-                bytes.len() as u32,
+                bytes.len() as c_uint,
                 flags as u32,
                 **scratch,
                 mem::transmute(callback),
                 mem::transmute(context),
             ));
-
-            trace!(
-                "block scan {} bytes with {} database at {:p}",
-                bytes.len(),
-                self.database_name(),
-                **self
-            )
         }
 
+        trace!(
+            "block scan {} bytes with {} database at {:p}",
+            bytes.len(),
+            self.database_name(),
+            **self
+        );
+
         Ok(&self)
     }
 }
 
-impl<T: Scannable, S: Scratch> VectoredScanner<T, S> for VectoredDatabase {
-    #[inline]
-    fn scan<D>(
+impl VectoredDatabase {
+    // The segments larger than `limit` bytes are split,
+    // which doesn't change the matches since the segments are scanned as a whole.
+    pub(crate) fn scan_vector<T: Scannable, S: Scratch, D>(
         &self,
-        data: &Vec<T>,
+        data: &[T],
+        limit: usize,
         flags: ScanFlags,
         scratch: &S,
         callback: Option<MatchEventCallback<D>>,
@@ -172,11 +185,26 @@ impl<T: Scannable, S: Scratch> VectoredScanner<T, S> for VectoredDatabase {
     ) -> Result<&Self, Error> {
         let mut ptrs = Vec::with_capacity(data.len());
         let mut lens = Vec::with_capacity(data.len());
+        let mut total: u64 = 0;
 
         for d in data.iter() {
             let bytes = d.as_bytes();
-            ptrs.push(bytes.as_ptr() as *const i8);
-            lens.push(bytes.len() as c_uint);
+
+            if bytes.is_empty() {
+                ptrs.push(bytes.as_ptr() as *const i8);
+                lens.push(0);
+            }
+
+            for chunk in bytes.chunks(limit) {
+                ptrs.push(chunk.as_ptr() as *const i8);
+                lens.push(chunk.len() as c_uint);
+            }
+
+            total += bytes.len() as u64;
+        }
+
+        if ptrs.len() > MAX_SCAN_LEN {
+            return Err(Error::TooLarge(ptrs.len()));
         }
 
         unsafe {
@@ -184,7 +212,7 @@ impl<T: Scannable, S: Scratch> VectoredScanner<T, S> for VectoredDatabase {
                 **self,
                 ptrs.as_slice().as_ptr() as *const *const i8,
                 lens.as_slice().as_ptr() as *const c_uint,
-                data.len() as u32,
+                ptrs.len() as c_uint,
                 flags as u32,
                 **scratch,
                 mem::transmute(callback),
@@ -194,7 +222,7 @@ impl<T: Scannable, S: Scratch> VectoredScanner<T, S> for VectoredDatabase {
 
         trace!(
             "vectored scan {} bytes in {} parts with {} database at {:p}",
-            lens.iter().fold(0, |sum, len| sum + len),
+            total,
             lens.len(),
             self.database_name(),
             **self
@@ -204,6 +232,20 @@ impl<T: Scannable, S: Scratch> VectoredScanner<T, S> for VectoredDatabase {
     }
 }
 
+impl<T: Scannable, S: Scratch> VectoredScanner<T, S> for VectoredDatabase {
+    #[inline]
+    fn scan<D>(
+        &self,
+        data: &[T],
+        flags: ScanFlags,
+        scratch: &S,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&D>,
+    ) -> Result<&Self, Error> {
+        self.scan_vector(data, MAX_SCAN_LEN, flags, scratch, callback, context)
+    }
+}
+
 impl StreamingScanner<RawStream, RawScratch> for StreamingDatabase {
     fn open_stream(&self, flags: StreamFlags) -> Result<RawStream, Error> {
         let mut id: RawStreamPtr = ptr::null_mut();
@@ -262,13 +304,76 @@ impl Clone for RawStream {
     }
 }
 
-impl<S: Scratch> Stream<S> for RawStream {
-    fn close<D>(
+// The stream state can be moved to another thread, but must not be used concurrently.
+unsafe impl Send for RawStream {}
+
+impl RawStream {
+    // The data larger than `limit` bytes is scanned in chunks,
+    // the stream keeps the state and the absolute offsets across them.
+    pub(crate) fn scan_chunks<S: Scratch, D>(
         &self,
+        bytes: &[u8],
+        limit: usize,
+        flags: ScanFlags,
         scratch: &S,
         callback: Option<MatchEventCallback<D>>,
         context: Option<&D>,
     ) -> Result<&Self, Error> {
+        for chunk in bytes.chunks(limit) {
+            unsafe {
+                check_hs_error!(hs_scan_stream(
+                    self.0,
+                    chunk.as_ptr() as *const i8,
+                    chunk.len() as c_uint,
+                    flags as u32,
+                    **scratch,
+                    mem::transmute(callback),
+                    mem::transmute(context),
+                ));
+            }
+        }
+
+        trace!("stream scan {} bytes with stream at {:p}", bytes.len(), self.0);
+
+        Ok(&self)
+    }
+
+    /// Close a stream and discard any pending end-of-data (EOD) matches.
+    pub fn discard(mut self) -> Result<(), Error> {
+        unsafe {
+            check_hs_error!(hs_close_stream(self.0, ptr::null_mut(), None, ptr::null_mut()));
+        }
+
+        trace!("stream discarded at {:p}", self.0);
+
+        self.0 = ptr::null_mut();
+
+        Ok(())
+    }
+}
+
+impl Drop for RawStream {
+    #[inline]
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe {
+                assert_hs_error!(hs_close_stream(self.0, ptr::null_mut(), None, ptr::null_mut()));
+            }
+
+            trace!("stream dropped at {:p}", self.0);
+
+            self.0 = ptr::null_mut();
+        }
+    }
+}
+
+impl<S: Scratch> Stream<S> for RawStream {
+    fn close<D>(
+        mut self,
+        scratch: &S,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&D>,
+    ) -> Result<(), Error> {
         unsafe {
             check_hs_error!(hs_close_stream(
                 self.0,
@@ -280,7 +385,10 @@ impl<S: Scratch> Stream<S> for RawStream {
 
         trace!("stream closed at {:p}", self.0);
 
-        Ok(&self)
+        // the stream state has been freed by Hyperscan
+        self.0 = ptr::null_mut();
+
+        Ok(())
     }
 
     fn reset<D>(
@@ -304,45 +412,53 @@ impl<S: Scratch> Stream<S> for RawStream {
 
         Ok(&self)
     }
-}
 
-impl<T: Scannable, S: Scratch> BlockScanner<T, S> for RawStream {
-    #[inline]
-    fn scan<D>(
+    fn reset_and_copy<D>(
         &self,
-        data: T,
-        flags: ScanFlags,
+        from: &Self,
         scratch: &S,
         callback: Option<MatchEventCallback<D>>,
         context: Option<&D>,
     ) -> Result<&Self, Error> {
-        let bytes = data.as_bytes();
-
         unsafe {
-            check_hs_error!(hs_scan_stream(
+            check_hs_error!(hs_reset_and_copy_stream(
                 self.0,
-                bytes.as_ptr() as *const i8,
-                bytes.len() as u32,
-                flags as u32,
+                from.0,
                 **scratch,
                 mem::transmute(callback),
                 mem::transmute(context),
             ));
         }
 
-        trace!("stream scan {} bytes with stream at {:p}", bytes.len(), self.0);
+        trace!("stream reset and copied from {:p} to {:p}", from.0, self.0);
 
         Ok(&self)
     }
 }
 
+impl<T: Scannable, S: Scratch> BlockScanner<T, S> for RawStream {
+    #[inline]
+    fn scan<D>(
+        &self,
+        data: T,
+        flags: ScanFlags,
+        scratch: &S,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&D>,
+    ) -> Result<&Self, Error> {
+        self.scan_chunks(data.as_bytes(), MAX_SCAN_LEN, flags, scratch, callback, context)
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     extern crate env_logger;
 
+    use std::cell::RefCell;
     use std::ptr;
 
     use super::super::*;
+    use super::super::harness::on_match;
 
     const SCRATCH_SIZE: usize = 2000;
 
@@ -455,4 +571,74 @@ pub mod tests {
 
         st.close(&s, Some(callback), Some(&db)).unwrap();
     }
+
+    #[test]
+    fn test_scan_large_data_in_chunks() {
+        let _ = env_logger::try_init();
+
+        let matches = RefCell::new(Vec::new());
+
+        let db: VectoredDatabase = pattern!{"test"}.build().unwrap();
+        let s = RawScratch::alloc(&db).unwrap();
+        let data = vec!["foo te", "", "st bar test"];
+
+        db.scan_vector(&data, 3, 0, &s, Some(on_match), Some(&matches))
+            .unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(0, 8), (0, 17)]);
+
+        matches.borrow_mut().clear();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+        let s = RawScratch::alloc(&db).unwrap();
+        let st = db.open_stream(0).unwrap();
+
+        st.scan_chunks(b"foo test bar", 2, 0, &s, Some(on_match), Some(&matches))
+            .unwrap();
+        st.scan_chunks(b" test", 1, 0, &s, Some(on_match), Some(&matches))
+            .unwrap();
+        st.close(&s, Some(on_match), Some(&matches)).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(0, 8), (0, 17)]);
+    }
+
+    #[test]
+    fn test_stream_reset_and_copy() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = pattern!{"test$", flags => HS_FLAG_CASELESS}.build().unwrap();
+
+        let s = RawScratch::alloc(&db).unwrap();
+        let st = db.open_stream(0).unwrap();
+        let st2 = db.open_stream(0).unwrap();
+
+        let matches = RefCell::new(Vec::new());
+
+        st.scan("foo te", 0, &s, Some(on_match), Some(&matches)).unwrap();
+        st2.scan("foo test", 0, &s, Some(on_match), Some(&matches)).unwrap();
+
+        // the pending EOD match of `st2` is reported before it is overwritten
+        st2.reset_and_copy(&st, &s, Some(on_match), Some(&matches)).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(0, 8)]);
+
+        st2.scan("st", 0, &s, Some(on_match), Some(&matches)).unwrap();
+        st2.close(&s, Some(on_match), Some(&matches)).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(0, 8), (0, 8)]);
+
+        st.scan("st", 0, &s, Some(on_match), Some(&matches)).unwrap();
+        st.discard().unwrap();
+
+        assert_eq!(matches.borrow().len(), 2);
+
+        // dropping a stream frees its state without reporting
+        let st3 = db.open_stream(0).unwrap();
+
+        st3.scan("test", 0, &s, Some(on_match), Some(&matches)).unwrap();
+
+        drop(st3);
+
+        assert_eq!(matches.borrow().len(), 2);
+    }
 }
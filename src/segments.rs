@@ -0,0 +1,257 @@
+#[cfg(feature = "bytes")]
+use std::io::IoSlice;
+
+#[cfg(feature = "bytes")]
+use bytes::Buf;
+
+use api::*;
+#[cfg(feature = "bytes")]
+use common::VectoredDatabase;
+#[cfg(feature = "bytes")]
+use errors::Error;
+
+/// A position in the segments of a vectored scan.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SegmentOffset {
+    /// The index of the segment.
+    pub segment: usize,
+    /// The offset in the segment.
+    pub offset: usize,
+}
+
+/// The layout of the segments of a vectored scan,
+/// which resolves the offsets in the virtual concatenation of the segments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segments {
+    /// The absolute end offset of each segment.
+    ends: Vec<u64>,
+}
+
+impl Segments {
+    /// Construct the layout of the segments to be scanned.
+    pub fn new<T: Scannable>(data: &[T]) -> Segments {
+        Segments::from_lens(data.iter().map(|d| d.as_bytes().len()))
+    }
+
+    /// Construct the layout from the length of each segment.
+    pub fn from_lens<I: IntoIterator<Item = usize>>(lens: I) -> Segments {
+        let mut end = 0;
+
+        Segments {
+            ends: lens.into_iter()
+                .map(|len| {
+                    end += len as u64;
+                    end
+                })
+                .collect(),
+        }
+    }
+
+    /// The number of segments.
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Whether there is no segment.
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// The total length of the segments.
+    pub fn total_len(&self) -> u64 {
+        self.ends.last().cloned().unwrap_or(0)
+    }
+
+    fn start(&self, segment: usize) -> u64 {
+        if segment == 0 {
+            0
+        } else {
+            self.ends[segment - 1]
+        }
+    }
+
+    // the index of the first segment which ends after `offset` (or at it, if `inclusive`)
+    fn search(&self, offset: u64, inclusive: bool) -> usize {
+        let (mut lo, mut hi) = (0, self.ends.len());
+
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+
+            if self.ends[mid] < offset || (!inclusive && self.ends[mid] == offset) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        lo
+    }
+
+    /// Resolve the start offset of a match to the segment which contains its first byte.
+    ///
+    /// An empty match at the end of the data is resolved to the end of the last segment.
+    pub fn start_of(&self, from: u64) -> Option<SegmentOffset> {
+        let segment = self.search(from, false);
+
+        if segment < self.ends.len() {
+            Some(SegmentOffset {
+                segment: segment,
+                offset: (from - self.start(segment)) as usize,
+            })
+        } else if from == self.total_len() && !self.ends.is_empty() {
+            self.end_of(from)
+        } else {
+            None
+        }
+    }
+
+    /// Resolve the end offset of a match to the segment which contains its last byte.
+    pub fn end_of(&self, to: u64) -> Option<SegmentOffset> {
+        let segment = self.search(to, true);
+
+        if segment < self.ends.len() {
+            Some(SegmentOffset {
+                segment: segment,
+                offset: (to - self.start(segment)) as usize,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Resolve the start and end offsets of a match.
+    pub fn resolve(&self, from: u64, to: u64) -> Option<(SegmentOffset, SegmentOffset)> {
+        match (self.start_of(from), self.end_of(to)) {
+            (Some(start), Some(end)) => Some((start, end)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl VectoredDatabase {
+    /// Scan the chunks of a `bytes::Buf` (such as a `Chain`) as the segments of a vectored scan,
+    /// without consuming it.
+    ///
+    /// Returns the layout of the scanned segments.
+    pub fn scan_buf<B: Buf, S: Scratch, D>(
+        &self,
+        buf: &B,
+        flags: ScanFlags,
+        scratch: &S,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&D>,
+    ) -> Result<Segments, Error> {
+        let mut slices = vec![IoSlice::new(&[]); 16];
+
+        loop {
+            let n = buf.chunks_vectored(&mut slices);
+            let len = slices[..n].iter().fold(0, |len, s| len + s.len());
+
+            if len == buf.remaining() {
+                slices.truncate(n);
+
+                break;
+            }
+
+            // the buffer doesn't expose all of its chunks
+            if n < slices.len() {
+                return Err(Error::Invalid);
+            }
+
+            let size = slices.len() * 2;
+
+            slices = vec![IoSlice::new(&[]); size];
+        }
+
+        try!(self.scan(&slices, flags, scratch, callback, context));
+
+        Ok(Segments::new(&slices))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+    use std::io::IoSlice;
+
+    use super::super::*;
+
+    #[test]
+    fn test_segments() {
+        let segments = Segments::new(&["foo", "", "test", "bar"]);
+
+        assert_eq!(segments.len(), 4);
+        assert_eq!(segments.total_len(), 10);
+
+        let at = |segment, offset| SegmentOffset {
+            segment: segment,
+            offset: offset,
+        };
+
+        assert_eq!(segments.start_of(0), Some(at(0, 0)));
+        assert_eq!(segments.start_of(3), Some(at(2, 0)));
+        assert_eq!(segments.end_of(3), Some(at(0, 3)));
+        assert_eq!(segments.end_of(7), Some(at(2, 4)));
+        assert_eq!(segments.start_of(10), Some(at(3, 3)));
+        assert_eq!(segments.resolve(3, 7), Some((at(2, 0), at(2, 4))));
+        assert_eq!(segments.resolve(5, 9), Some((at(2, 2), at(3, 2))));
+        assert_eq!(segments.start_of(11), None);
+        assert_eq!(segments.end_of(11), None);
+
+        assert_eq!(Segments::from_lens(vec![]).start_of(0), None);
+    }
+
+    fn callback(id: u32, from: u64, to: u64, _: u32, matches: &RefCell<Vec<(u32, u64, u64)>>) -> u32 {
+        matches.borrow_mut().push((id, from, to));
+
+        0
+    }
+
+    #[test]
+    fn test_vectored_scan_io_slices() {
+        let _ = env_logger::try_init();
+
+        let db: VectoredDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let data = [IoSlice::new(b"foo te"), IoSlice::new(b"st bar")];
+        let matches = RefCell::new(Vec::new());
+
+        db.scan(&data[..], 0, &s, Some(callback), Some(&matches)).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(0, 4, 8)]);
+
+        let segments = Segments::new(&data);
+
+        assert_eq!(
+            segments.resolve(4, 8),
+            Some((
+                SegmentOffset { segment: 0, offset: 4 },
+                SegmentOffset { segment: 1, offset: 2 }
+            ))
+        );
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_vectored_scan_buf() {
+        use bytes::Buf;
+
+        let _ = env_logger::try_init();
+
+        let db: VectoredDatabase = pattern!{"test", flags => HS_FLAG_SOM_LEFTMOST}.build().unwrap();
+        let s = db.alloc().unwrap();
+
+        let buf = (&b"foo te"[..]).chain(&b"st "[..]).chain(&b"bar"[..]);
+        let matches = RefCell::new(Vec::new());
+
+        let segments = db.scan_buf(&buf, 0, &s, Some(callback), Some(&matches)).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(0, 4, 8)]);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments.end_of(8), Some(SegmentOffset { segment: 1, offset: 2 }));
+    }
+}
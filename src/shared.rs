@@ -0,0 +1,413 @@
+use std::ffi::CString;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::mem::{self, ManuallyDrop};
+use std::ops::Deref;
+use std::os::unix::io::FromRawFd;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use libc;
+
+use api::*;
+use common::{check_mode, RawDatabase};
+use constants::*;
+use errors::{Error, SharedError};
+use raw::*;
+
+/// The magic number at the beginning of the shared database regions.
+const SHARED_MAGIC: u64 = 0x4853_4442_5348_4d31; // "HSDBSHM1"
+
+/// The offset of the database in the region, which keeps the alignment required by Hyperscan.
+const DATABASE_OFFSET: usize = 64;
+
+/// The header of a shared database region.
+#[repr(C)]
+struct Header {
+    magic: u64,
+    generation: u64,
+    mode: u32,
+    reserved: u32,
+    size: u64,
+}
+
+/// A shared memory mapping, which is unmapped when dropped.
+struct Mapping {
+    p: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(file: &File, len: usize, prot: libc::c_int) -> io::Result<Mapping> {
+        use std::os::unix::io::AsRawFd;
+
+        let p = unsafe { libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, file.as_raw_fd(), 0) };
+
+        if p == libc::MAP_FAILED {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Mapping { p: p as *mut u8, len: len })
+        }
+    }
+
+    fn protect(&self, prot: libc::c_int) -> io::Result<()> {
+        if unsafe { libc::mprotect(self.p as *mut libc::c_void, self.len, prot) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.p as *const Header) }
+    }
+
+    /// The current generation in the control region.
+    fn generation(&self) -> &AtomicU64 {
+        unsafe { &*(self.p as *const AtomicU64) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.p as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+/// The path of the control region with `None`, or the database region of the generation.
+fn shm_path(name: &str, generation: Option<u64>) -> Result<CString, Error> {
+    if name.is_empty() || name.contains('/') {
+        return Err(Error::Invalid);
+    }
+
+    let path = match generation {
+        Some(generation) => format!("/{}.{}", name, generation),
+        None => format!("/{}", name),
+    };
+
+    Ok(try!(CString::new(path)))
+}
+
+fn shm_open(path: &CString, flags: libc::c_int) -> io::Result<File> {
+    let fd = unsafe { libc::shm_open(path.as_ptr(), flags, 0o644 as libc::mode_t) };
+
+    if fd < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+}
+
+fn shm_unlink(path: &CString) -> io::Result<()> {
+    if unsafe { libc::shm_unlink(path.as_ptr()) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Unlink a new region when dropped, unless it has been published.
+struct UnlinkGuard<'a> {
+    path: &'a CString,
+    armed: bool,
+}
+
+impl<'a> Drop for UnlinkGuard<'a> {
+    fn drop(&mut self) {
+        if self.armed {
+            if let Err(err) = shm_unlink(self.path) {
+                debug!("fail to unlink unpublished region {:?}, {}", self.path, err);
+            }
+        }
+    }
+}
+
+/// Open the control region of the name, which holds the current generation.
+fn open_control(name: &str, create: bool) -> Result<Mapping, SharedError> {
+    let path = try!(shm_path(name, None));
+    let len = mem::size_of::<u64>();
+
+    let (file, prot) = if create {
+        let file = try!(shm_open(&path, libc::O_RDWR | libc::O_CREAT));
+
+        if try!(file.metadata()).len() < len as u64 {
+            try!(file.set_len(len as u64));
+        }
+
+        (file, libc::PROT_READ | libc::PROT_WRITE)
+    } else {
+        (try!(shm_open(&path, libc::O_RDONLY)), libc::PROT_READ)
+    };
+
+    Ok(try!(Mapping::new(&file, len, prot)))
+}
+
+/// A pattern database deserialized in a named shared memory region,
+/// which is published by one process and attached read-only by the others without copying.
+///
+/// Each publishing creates a new region of the next generation, so a reload never modifies
+/// the database which is scanned by the attached processes. The old region is unlinked and
+/// freed once all the processes have detached from it.
+///
+/// The database is scanned through `Deref`, which also exposes `SerializableDatabase::deserialize_at`.
+/// It must not be called on a shared database: the region is read-only once published,
+/// so writing a database into it faults instead of returning an error.
+pub struct SharedDatabase<T: Type> {
+    name: String,
+    generation: u64,
+    control: Mapping,
+    region: Mapping,
+    db: ManuallyDrop<RawDatabase<T>>,
+}
+
+impl<T: Type> fmt::Debug for SharedDatabase<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SharedDatabase<{}>{{name: {:?}, generation: {}, db: {:p}, len: {}}}",
+            T::name(),
+            self.name,
+            self.generation,
+            **self.db,
+            self.region.len
+        )
+    }
+}
+
+impl<T: Type> SharedDatabase<T> {
+    /// Deserialize the database into a new shared memory region of the name,
+    /// and make it the current generation for the processes attaching to the name.
+    ///
+    /// The region of the previous generation is unlinked. Only one process should publish a name.
+    pub fn publish(name: &str, bytes: &[u8]) -> Result<SharedDatabase<T>, SharedError> {
+        try!(check_mode::<T>(bytes));
+
+        let size = try!(bytes.database_size());
+        let control = try!(open_control(name, true));
+        let previous = control.generation().load(Ordering::Acquire);
+        let generation = previous + 1;
+
+        let path = try!(shm_path(name, Some(generation)));
+        let file = try!(shm_open(&path, libc::O_RDWR | libc::O_CREAT | libc::O_EXCL));
+        let mut guard = UnlinkGuard {
+            path: &path,
+            armed: true,
+        };
+        let len = DATABASE_OFFSET + size;
+
+        try!(file.set_len(len as u64));
+
+        let region = try!(Mapping::new(&file, len, libc::PROT_READ | libc::PROT_WRITE));
+
+        unsafe {
+            let db = region.p.offset(DATABASE_OFFSET as isize) as RawDatabasePtr;
+            let res = hs_deserialize_database_at(bytes.as_ptr() as *const i8, bytes.len(), db);
+
+            if res != HS_SUCCESS {
+                return Err(Error::from(res).into());
+            }
+
+            ptr::write(
+                region.p as *mut Header,
+                Header {
+                    magic: SHARED_MAGIC,
+                    generation: generation,
+                    mode: T::mode(),
+                    reserved: 0,
+                    size: size as u64,
+                },
+            );
+        }
+
+        try!(region.protect(libc::PROT_READ));
+
+        control.generation().store(generation, Ordering::Release);
+
+        guard.armed = false;
+
+        if previous > 0 {
+            if let Err(err) = shm_unlink(&try!(shm_path(name, Some(previous)))) {
+                debug!("fail to unlink generation {} of shared database `{}`, {}", previous, name, err);
+            }
+        }
+
+        debug!(
+            "published {} database of {} bytes as generation {} of shared database `{}`",
+            T::name(),
+            size,
+            generation,
+            name
+        );
+
+        Ok(SharedDatabase::from_region(name, generation, control, region))
+    }
+
+    /// Attach to the current generation of the shared database of the name, read-only.
+    pub fn attach(name: &str) -> Result<SharedDatabase<T>, SharedError> {
+        let control = try!(open_control(name, false));
+
+        loop {
+            let generation = control.generation().load(Ordering::Acquire);
+
+            if generation == 0 {
+                return Err(Error::Invalid.into());
+            }
+
+            let file = match shm_open(&try!(shm_path(name, Some(generation))), libc::O_RDONLY) {
+                Ok(file) => file,
+                // the generation has been replaced since it was read
+                Err(ref err)
+                    if err.kind() == io::ErrorKind::NotFound
+                        && control.generation().load(Ordering::Acquire) != generation =>
+                {
+                    continue
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            let len = try!(file.metadata()).len() as usize;
+
+            if len < DATABASE_OFFSET {
+                return Err(Error::Invalid.into());
+            }
+
+            let region = try!(Mapping::new(&file, len, libc::PROT_READ));
+
+            {
+                let header = region.header();
+
+                if header.magic != SHARED_MAGIC
+                    || header.generation != generation
+                    || header.size as usize > len - DATABASE_OFFSET
+                {
+                    return Err(Error::Invalid.into());
+                }
+                if header.mode != T::mode() {
+                    return Err(Error::DbModeError.into());
+                }
+            }
+
+            debug!("attached to generation {} of shared database `{}`", generation, name);
+
+            return Ok(SharedDatabase::from_region(name, generation, control, region));
+        }
+    }
+
+    fn from_region(name: &str, generation: u64, control: Mapping, region: Mapping) -> SharedDatabase<T> {
+        let db = unsafe { region.p.offset(DATABASE_OFFSET as isize) } as RawDatabasePtr;
+
+        SharedDatabase {
+            name: name.to_owned(),
+            generation: generation,
+            control: control,
+            region: region,
+            db: ManuallyDrop::new(RawDatabase::from_raw(db)),
+        }
+    }
+
+    /// The name of the shared database.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The generation of the attached region.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether a newer generation has been published, and the processes should attach to it.
+    pub fn is_current(&self) -> bool {
+        self.control.generation().load(Ordering::Acquire) == self.generation
+    }
+
+    /// Attach to the current generation, if a newer one has been published.
+    pub fn reload(&mut self) -> Result<bool, SharedError> {
+        if self.is_current() {
+            return Ok(false);
+        }
+
+        *self = try!(SharedDatabase::attach(&self.name));
+
+        Ok(true)
+    }
+
+    /// Remove the name and its current region, the attached processes are not affected.
+    pub fn unlink(name: &str) -> Result<(), SharedError> {
+        let generation = try!(open_control(name, false)).generation().load(Ordering::Acquire);
+
+        try!(shm_unlink(&try!(shm_path(name, None))));
+
+        if generation > 0 {
+            try!(shm_unlink(&try!(shm_path(name, Some(generation)))));
+        }
+
+        Ok(())
+    }
+}
+
+/// Scan the shared database, don't call `deserialize_at` on it.
+impl<T: Type> Deref for SharedDatabase<T> {
+    type Target = RawDatabase<T>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.db
+    }
+}
+
+unsafe impl<T: Type> Send for SharedDatabase<T> {}
+unsafe impl<T: Type> Sync for SharedDatabase<T> {}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::process;
+
+    use super::super::*;
+
+    #[test]
+    fn test_shared_database() {
+        let _ = env_logger::try_init();
+
+        let name = format!("hyperscan-test-{}", process::id());
+
+        let db: BlockDatabase = pattern!{"test"}.build().unwrap();
+        let data = db.serialize().unwrap();
+
+        let published = SharedDatabase::<Block>::publish(&name, data.as_slice()).unwrap();
+
+        assert_eq!(published.generation(), 1);
+
+        let mut attached = SharedDatabase::<Block>::attach(&name).unwrap();
+
+        assert_eq!(attached.generation(), 1);
+        assert!(attached.is_current());
+        assert_eq!(
+            SharedDatabase::<Streaming>::attach(&name).err().map(|err| err.to_string()),
+            Some(SharedError::Database(Error::DbModeError).to_string())
+        );
+
+        let s = attached.alloc().unwrap();
+
+        attached.scan::<()>("foo test bar", 0, &s, None, None).unwrap();
+
+        let db: BlockDatabase = patterns!(["foo", "bar"]).build().unwrap();
+        let data = db.serialize().unwrap();
+
+        SharedDatabase::<Block>::publish(&name, data.as_slice()).unwrap();
+
+        // the old generation is still mapped and can be scanned
+        assert!(!attached.is_current());
+        attached.scan::<()>("foo test bar", 0, &s, None, None).unwrap();
+
+        assert!(attached.reload().unwrap());
+        assert_eq!(attached.generation(), 2);
+        assert!(!published.is_current());
+
+        SharedDatabase::<Block>::unlink(&name).unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use api::*;
+use common::StreamingDatabase;
+use errors::Error;
+use runtime::{RawScratch, RawStream};
+
+/// Definition of the match event callback function type for the keyed streams.
+///
+/// It is the same as `MatchEventCallback`, with the key of the stream that has matched.
+///
+/// Fn(key: &K, id: u32, from: u64, to: u64, flags: u32) -> bool
+///
+pub type FlowMatchCallback<K, D> = fn(key: &K, id: u32, from: u64, to: u64, flags: u32, data: &D) -> u32;
+
+struct FlowContext<'a, K: 'a, D: 'a> {
+    key: &'a K,
+    callback: FlowMatchCallback<K, D>,
+    context: &'a D,
+}
+
+fn on_flow_match<K, D>(id: u32, from: u64, to: u64, flags: u32, ctx: &FlowContext<K, D>) -> u32 {
+    (ctx.callback)(ctx.key, id, from, to, flags, ctx.context)
+}
+
+struct Flow {
+    stream: RawStream,
+    seq: u64,
+    last_seen: Instant,
+}
+
+/// A table of streams keyed by flow, opened on demand.
+///
+/// The streams are closed on explicit end, idle timeout or LRU eviction,
+/// and any end-of-data (EOD) matches are reported to the callback with the key of the stream.
+///
+/// Dropping the table discards the live streams without reporting their EOD matches,
+/// call `close_all` before to report them.
+pub struct StreamTable<'a, K: Hash + Eq + Clone, D: 'a> {
+    db: &'a StreamingDatabase,
+    scratch: RawScratch,
+    callback: FlowMatchCallback<K, D>,
+    context: &'a D,
+    flows: HashMap<K, Flow>,
+    lru: BTreeMap<u64, K>,
+    seq: u64,
+    max_streams: usize,
+    idle_timeout: Option<Duration>,
+}
+
+impl<'a, K: Hash + Eq + Clone, D: 'a> fmt::Debug for StreamTable<'a, K, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "StreamTable{{db: {:?}, streams: {}, max_streams: {}}}",
+            self.db,
+            self.flows.len(),
+            self.max_streams
+        )
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, D: 'a> StreamTable<'a, K, D> {
+    /// Construct a table which keeps at most `max_streams` live streams opened against the database.
+    pub fn new(
+        db: &'a StreamingDatabase,
+        max_streams: usize,
+        callback: FlowMatchCallback<K, D>,
+        context: &'a D,
+    ) -> Result<StreamTable<'a, K, D>, Error> {
+        if max_streams == 0 {
+            return Err(Error::Invalid);
+        }
+
+        Ok(StreamTable {
+            db: db,
+            scratch: try!(db.alloc()),
+            callback: callback,
+            context: context,
+            flows: HashMap::new(),
+            lru: BTreeMap::new(),
+            seq: 0,
+            max_streams: max_streams,
+            idle_timeout: None,
+        })
+    }
+
+    /// Close the streams which have not been scanned for `timeout` when `expire` is called.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.idle_timeout = timeout;
+
+        self
+    }
+
+    /// The number of live streams.
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    /// Whether the table has no live stream.
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Whether a live stream exists for the key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.flows.contains_key(key)
+    }
+
+    /// The total size of the live stream states in bytes.
+    pub fn memory_usage(&self) -> Result<usize, Error> {
+        Ok(try!(self.db.stream_size()) * self.flows.len())
+    }
+
+    /// Scan a chunk of data with the stream of the key, opening it when needed.
+    ///
+    /// The least recently used stream will be closed when the table is full.
+    pub fn scan<T: Scannable>(&mut self, key: &K, data: T) -> Result<(), Error> {
+        if !self.flows.contains_key(key) {
+            while self.flows.len() >= self.max_streams {
+                let lru = match self.lru.keys().next() {
+                    Some(&seq) => self.lru[&seq].clone(),
+                    None => break,
+                };
+
+                debug!("evict the least recently used stream of {} streams", self.flows.len());
+
+                try!(self.close(&lru));
+            }
+
+            let stream = try!(self.db.open_stream(0));
+
+            self.flows.insert(
+                key.clone(),
+                Flow {
+                    stream: stream,
+                    seq: 0,
+                    last_seen: Instant::now(),
+                },
+            );
+        }
+
+        self.seq += 1;
+
+        let seq = self.seq;
+        let flow = self.flows.get_mut(key).unwrap();
+
+        self.lru.remove(&flow.seq);
+        self.lru.insert(seq, key.clone());
+
+        flow.seq = seq;
+        flow.last_seen = Instant::now();
+
+        let ctx = FlowContext {
+            key: key,
+            callback: self.callback,
+            context: self.context,
+        };
+
+        try!(flow.stream.scan(
+            data,
+            0,
+            &self.scratch,
+            Some(on_flow_match::<K, D> as MatchEventCallback<FlowContext<K, D>>),
+            Some(&ctx),
+        ));
+
+        Ok(())
+    }
+
+    /// Close the stream of the key, reporting any EOD matches.
+    ///
+    /// Returns `false` if there is no live stream for the key.
+    pub fn close(&mut self, key: &K) -> Result<bool, Error> {
+        match self.flows.remove(key) {
+            Some(flow) => {
+                self.lru.remove(&flow.seq);
+
+                let ctx = FlowContext {
+                    key: key,
+                    callback: self.callback,
+                    context: self.context,
+                };
+
+                try!(flow.stream.close(
+                    &self.scratch,
+                    Some(on_flow_match::<K, D> as MatchEventCallback<FlowContext<K, D>>),
+                    Some(&ctx),
+                ));
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Close the streams which have been idle longer than the idle timeout at `now`,
+    /// reporting any EOD matches.
+    ///
+    /// Returns the number of closed streams.
+    pub fn expire(&mut self, now: Instant) -> Result<usize, Error> {
+        let timeout = match self.idle_timeout {
+            Some(timeout) => timeout,
+            None => return Ok(0),
+        };
+
+        let expired: Vec<K> = self.flows
+            .iter()
+            .filter(|&(_, flow)| now > flow.last_seen && now.duration_since(flow.last_seen) >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &expired {
+            try!(self.close(key));
+        }
+
+        debug!("expired {} idle streams", expired.len());
+
+        Ok(expired.len())
+    }
+
+    /// Close all the live streams, reporting any EOD matches.
+    ///
+    /// Returns the number of closed streams.
+    pub fn close_all(&mut self) -> Result<usize, Error> {
+        let keys: Vec<K> = self.flows.keys().cloned().collect();
+
+        for key in &keys {
+            try!(self.close(key));
+        }
+
+        Ok(keys.len())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+    use std::time::{Duration, Instant};
+
+    use super::super::*;
+
+    #[test]
+    fn test_stream_table() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = pattern!{"test$"}.build().unwrap();
+
+        fn callback(key: &u32, id: u32, _: u64, to: u64, _: u32, matches: &RefCell<Vec<(u32, u32, u64)>>) -> u32 {
+            matches.borrow_mut().push((*key, id, to));
+
+            0
+        }
+
+        let matches = RefCell::new(Vec::new());
+        let mut table = StreamTable::new(&db, 2, callback, &matches).unwrap();
+
+        table.set_idle_timeout(Some(Duration::from_secs(60)));
+
+        table.scan(&1, "foo te").unwrap();
+        table.scan(&2, "test").unwrap();
+        table.scan(&1, "st").unwrap();
+
+        assert_eq!(table.len(), 2);
+        assert!(matches.borrow().is_empty());
+
+        // the least recently used stream of key 2 is evicted
+        table.scan(&3, "bar").unwrap();
+
+        assert_eq!(table.len(), 2);
+        assert!(!table.contains_key(&2));
+        assert_eq!(*matches.borrow(), vec![(2, 0, 4)]);
+        assert_eq!(table.memory_usage().unwrap(), db.stream_size().unwrap() * 2);
+
+        assert!(table.close(&1).unwrap());
+        assert!(!table.close(&1).unwrap());
+        assert_eq!(*matches.borrow(), vec![(2, 0, 4), (1, 0, 8)]);
+
+        assert_eq!(table.expire(Instant::now()).unwrap(), 0);
+        assert_eq!(table.expire(Instant::now() + Duration::from_secs(120)).unwrap(), 1);
+        assert!(table.is_empty());
+        assert_eq!(matches.borrow().len(), 2);
+
+        table.scan(&4, "test").unwrap();
+        table.scan(&5, "a test").unwrap();
+
+        assert_eq!(table.close_all().unwrap(), 2);
+        assert!(table.is_empty());
+
+        let mut eod = matches.borrow()[2..].to_vec();
+
+        eod.sort();
+
+        assert_eq!(eod, vec![(4, 0, 4), (5, 0, 6)]);
+    }
+}
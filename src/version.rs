@@ -0,0 +1,148 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::str::FromStr;
+
+use api::PlatformInfo;
+use errors::Error;
+use raw::*;
+
+/// The semantic version and the build date of the Hyperscan library.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// The build date of the library, such as `2018-07-09`.
+    pub date: Option<String>,
+}
+
+impl Version {
+    /// Construct a version without the build date.
+    pub fn new(major: u32, minor: u32, patch: u32) -> Version {
+        Version {
+            major: major,
+            minor: minor,
+            patch: patch,
+            date: None,
+        }
+    }
+
+    /// Whether the version is the same or later than the given one, ignoring the build date.
+    pub fn at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        (self.major, self.minor, self.patch) >= (major, minor, patch)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}.{}.{}", self.major, self.minor, self.patch));
+
+        if let Some(ref date) = self.date {
+            try!(write!(f, " {}", date));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    /// Parse the version string returned by `hs_version()`, such as `5.0.0 2018-07-09`.
+    fn from_str(s: &str) -> Result<Version, Error> {
+        let mut parts = s.split_whitespace();
+        let mut numbers = try!(parts.next().ok_or(Error::Invalid)).split('.');
+        let mut next = || -> Result<u32, Error> { Ok(try!(try!(numbers.next().ok_or(Error::Invalid)).parse())) };
+
+        let major = try!(next());
+        let minor = try!(next());
+        let patch = try!(next());
+
+        Ok(Version {
+            major: major,
+            minor: minor,
+            patch: patch,
+            date: parts.next().map(|date| date.to_owned()),
+        })
+    }
+}
+
+/// The version of the Hyperscan library linked at runtime.
+pub fn version() -> Result<Version, Error> {
+    let s = unsafe { CStr::from_ptr(hs_version()) };
+
+    s.to_str().map_err(|_| Error::Invalid).and_then(|s| s.parse())
+}
+
+/// The features of Hyperscan which the crate relies on, with the version introducing them.
+const REQUIRED_FEATURES: [(&str, (u32, u32, u32)); 2] = [
+    ("logical combination of patterns (`HS_FLAG_COMBINATION`)", (5, 0, 0)),
+    ("quiet matches (`HS_FLAG_QUIET`)", (5, 0, 0)),
+];
+
+/// Check that the Hyperscan library linked at runtime supports the features the crate relies on,
+/// and the current platform is supported by it.
+///
+/// It is intended to be called at startup, before compiling or loading any database.
+pub fn check_compatibility() -> Result<Version, Error> {
+    let version = try!(version());
+
+    debug!("check compatibility of Hyperscan {}", version);
+
+    for &(feature, (major, minor, patch)) in REQUIRED_FEATURES.iter() {
+        if !version.at_least(major, minor, patch) {
+            return Err(Error::Incompatible(format!(
+                "Hyperscan {} doesn't support {}, which requires {}.{}.{} or later",
+                version, feature, major, minor, patch
+            )));
+        }
+    }
+
+    if !PlatformInfo::is_valid() {
+        return Err(Error::Incompatible(format!(
+            "Hyperscan {} doesn't support the current platform",
+            version
+        )));
+    }
+
+    Ok(version)
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use super::super::*;
+
+    #[test]
+    fn test_parse_version() {
+        let v: Version = "5.0.0 2018-07-09".parse().unwrap();
+
+        assert_eq!(
+            v,
+            Version {
+                major: 5,
+                minor: 0,
+                patch: 0,
+                date: Some("2018-07-09".to_owned()),
+            }
+        );
+        assert_eq!(v.to_string(), "5.0.0 2018-07-09");
+        assert!(v.at_least(4, 7, 0));
+        assert!(!v.at_least(5, 1, 0));
+
+        assert_eq!("4.7.0".parse::<Version>().unwrap(), Version::new(4, 7, 0));
+        assert!("5.0".parse::<Version>().is_err());
+        assert!("".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn test_check_compatibility() {
+        let _ = env_logger::try_init();
+
+        let v = check_compatibility().unwrap();
+
+        assert_eq!(v, version().unwrap());
+        assert!(v.at_least(5, 0, 0));
+    }
+}
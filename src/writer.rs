@@ -0,0 +1,177 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use api::*;
+use common::StreamingDatabase;
+use errors::Error;
+use runtime::{RawScratch, RawStream};
+
+/// A `Write` adapter which scans the written bytes in a stream,
+/// optionally forwarding them to an inner writer.
+///
+/// The stream is closed by `flush` or `finish`, reporting any end-of-data (EOD) matches,
+/// the bytes written after that are rejected.
+///
+/// Dropping the writer without flushing or finishing it discards the EOD matches silently.
+///
+/// The bytes are forwarded to the inner writer before they are scanned, so a scan error,
+/// such as `Error::ScanTerminated` when the callback stops the scan, is reported
+/// by the next `write`, `flush` or `finish`, after the bytes are accepted.
+pub struct StreamWriter<'a, D: 'a, W: Write = io::Sink> {
+    stream: Option<RawStream>,
+    scratch: RawScratch,
+    callback: Option<MatchEventCallback<D>>,
+    context: Option<&'a D>,
+    inner: W,
+    /// The error of scanning the bytes already forwarded to the inner writer.
+    error: Option<Error>,
+}
+
+impl<'a, D: 'a, W: Write> fmt::Debug for StreamWriter<'a, D, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StreamWriter{{stream: {:?}, scratch: {:?}}}", self.stream, self.scratch)
+    }
+}
+
+impl<'a, D: 'a> StreamWriter<'a, D, io::Sink> {
+    /// Open a stream against the database to scan the written bytes.
+    pub fn new(
+        db: &StreamingDatabase,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&'a D>,
+    ) -> Result<StreamWriter<'a, D, io::Sink>, Error> {
+        StreamWriter::with_writer(db, io::sink(), callback, context)
+    }
+}
+
+impl<'a, D: 'a, W: Write> StreamWriter<'a, D, W> {
+    /// Open a stream against the database to scan the bytes written to the inner writer.
+    pub fn with_writer(
+        db: &StreamingDatabase,
+        inner: W,
+        callback: Option<MatchEventCallback<D>>,
+        context: Option<&'a D>,
+    ) -> Result<StreamWriter<'a, D, W>, Error> {
+        Ok(StreamWriter {
+            stream: Some(try!(db.open_stream(0))),
+            scratch: try!(db.alloc()),
+            callback: callback,
+            context: context,
+            inner: inner,
+            error: None,
+        })
+    }
+
+    /// Gets a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Close the stream, reporting any EOD matches, and return the inner writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        try!(self.close());
+
+        Ok(self.inner)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        if let Some(stream) = self.stream.take() {
+            try!(stream.close(&self.scratch, self.callback, self.context));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, D: 'a, W: Write> Write for StreamWriter<'a, D, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(err) = self.error.take() {
+            return Err(io::Error::new(io::ErrorKind::Other, err));
+        }
+
+        let stream = match self.stream {
+            Some(ref stream) => stream,
+            None => return Err(io::Error::new(io::ErrorKind::Other, Error::Invalid)),
+        };
+
+        let n = try!(self.inner.write(buf));
+
+        // the bytes were written, report the error with the next call
+        if let Err(err) = stream.scan(&buf[..n], 0, &self.scratch, self.callback, self.context) {
+            self.error = Some(err);
+        }
+
+        Ok(n)
+    }
+
+    /// Close the stream, reporting any EOD matches, and flush the inner writer.
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(io::Error::new(io::ErrorKind::Other, err));
+        }
+
+        try!(self.close().map_err(|err| io::Error::new(io::ErrorKind::Other, err)));
+
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate env_logger;
+
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+
+    use super::super::*;
+    use super::super::harness::{on_match, stop_on_match};
+
+    #[test]
+    fn test_stream_writer() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = patterns!(["test", "bar$"]).build().unwrap();
+
+        let matches = RefCell::new(Vec::new());
+        let mut w = StreamWriter::with_writer(&db, Vec::new(), Some(on_match), Some(&matches)).unwrap();
+
+        w.write_all(b"foo te").unwrap();
+        w.write_all(b"st ").unwrap();
+        io::copy(&mut io::Cursor::new("bar"), &mut w).unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(1, 8)]);
+
+        w.flush().unwrap();
+
+        assert_eq!(*matches.borrow(), vec![(1, 8), (2, 12)]);
+        assert!(w.write_all(b"test").is_err());
+
+        let data = w.finish().unwrap();
+
+        assert_eq!(data, b"foo test bar");
+        assert_eq!(*matches.borrow(), vec![(1, 8), (2, 12)]);
+    }
+
+    #[test]
+    fn test_stream_writer_terminated() {
+        let _ = env_logger::try_init();
+
+        let db: StreamingDatabase = pattern!{"test"}.build().unwrap();
+
+        let mut w = StreamWriter::with_writer(&db, Vec::new(), Some(stop_on_match), Some(&())).unwrap();
+
+        // the bytes are forwarded once, the scan error is reported by the next write
+        w.write_all(b"foo test bar").unwrap();
+
+        assert_eq!(w.get_ref(), b"foo test bar");
+
+        let err = w.write(b"baz").unwrap_err();
+
+        assert_eq!(err.get_ref().unwrap().to_string(), Error::ScanTerminated.to_string());
+        assert_eq!(w.get_ref(), b"foo test bar");
+    }
+}